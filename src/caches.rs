@@ -4,8 +4,13 @@ use ahash::{AHashMap, AHashSet};
 use rust_extensions::sorted_vec::{EntityWithKey, SortedVec};
 use crate::asset_symbol::AssetSymbol;
 use crate::assets::AssetPrice;
+use crate::errors::TradingError;
 use crate::instrument_symbol::InstrumentSymbol;
+use crate::instruments::{Instrument, InstrumentsRegistry};
+use crate::markups::MarkupProfiles;
 use crate::position_id::PositionId;
+use crate::price_history::PriceHistory;
+use crate::quote_filters::{QuoteCheckResult, QuoteFilter};
 use crate::wallet_id::WalletId;
 
 impl EntityWithKey<InstrumentSymbol> for BidAsk {
@@ -14,9 +19,18 @@ impl EntityWithKey<InstrumentSymbol> for BidAsk {
     }
 }
 
+/// Raw quotes with client quotes marked-up by trader groups
 #[derive(Clone, Debug)]
 pub struct BidAsksCache {
     items: SortedVec<InstrumentSymbol, BidAsk>,
+    markups: MarkupProfiles,
+    /// Client quotes marked-up by default profiles
+    default_client_items: SortedVec<InstrumentSymbol, BidAsk>,
+    /// Client quotes of trader groups with own profiles
+    client_items_by_groups: AHashMap<String, SortedVec<InstrumentSymbol, BidAsk>>,
+    filter: Option<QuoteFilter>,
+    history_capacity: Option<usize>,
+    histories: AHashMap<InstrumentSymbol, PriceHistory>,
 }
 
 impl BidAsksCache {
@@ -29,32 +43,144 @@ impl BidAsksCache {
 
         Self {
             items,
+            markups: MarkupProfiles::new(),
+            default_client_items: SortedVec::new(),
+            client_items_by_groups: AHashMap::new(),
+            filter: None,
+            history_capacity: None,
+            histories: AHashMap::new(),
         }
     }
 
-    pub fn update(&mut self, bidask: BidAsk) {
+    /// Enables history of accepted quotes with capacity by instrument. None disables and clears history
+    pub fn set_history_capacity(&mut self, capacity: Option<usize>) {
+        self.history_capacity = capacity;
+
+        let Some(capacity) = capacity else {
+            self.histories.clear();
+            return;
+        };
+
+        for history in self.histories.values_mut() {
+            history.set_capacity(capacity);
+        }
+    }
+
+    pub fn get_history(&self, instrument: &InstrumentSymbol) -> Option<&PriceHistory> {
+        self.histories.get(instrument)
+    }
+
+    /// Sets markups applied to accepted raw quotes. Client quotes of cached raw quotes are recalculated
+    pub fn set_markups(&mut self, markups: MarkupProfiles) {
+        self.markups = markups;
+        self.default_client_items = SortedVec::new();
+        self.client_items_by_groups.clear();
+        let raw_bidasks: Vec<BidAsk> = self.items.iter().cloned().collect();
+
+        for bidask in raw_bidasks.iter() {
+            self.update_client_bidasks(bidask);
+        }
+    }
+
+    pub fn get_markups(&self) -> &MarkupProfiles {
+        &self.markups
+    }
+
+    /// Sets filter checking each quote before it is accepted by update
+    pub fn set_filter(&mut self, filter: Option<QuoteFilter>) {
+        self.filter = filter;
+    }
+
+    pub fn get_filter(&self) -> Option<&QuoteFilter> {
+        self.filter.as_ref()
+    }
+
+    /// Accepts raw quote if it passes the filter and marks it up for trader groups.
+    /// Rejected and quarantined quotes are not stored
+    pub fn update(&mut self, bidask: BidAsk) -> QuoteCheckResult {
         let current_bidask = self.items.get_mut(&bidask.instrument);
 
+        if let Some(filter) = self.filter.as_mut() {
+            let result = filter.check(current_bidask.as_deref(), &bidask);
+
+            if !result.is_accepted() {
+                return result;
+            }
+        }
+
+        if let Some(capacity) = self.history_capacity {
+            self.histories
+                .entry(bidask.instrument.clone())
+                .or_insert_with(|| PriceHistory::new(capacity))
+                .add(bidask.clone());
+        }
+
         if let Some(current_bidask) = current_bidask {
-            _ = mem::replace(current_bidask, bidask);
+            _ = mem::replace(current_bidask, bidask.clone());
         } else {
-            self.items.insert_or_replace(bidask);
+            self.items.insert_or_replace(bidask.clone());
+        }
+
+        self.update_client_bidasks(&bidask);
+
+        QuoteCheckResult::Accepted
+    }
+
+    fn update_client_bidasks(&mut self, raw_bidask: &BidAsk) {
+        if self.markups.is_empty() {
+            return;
+        }
+
+        self.default_client_items.insert_or_replace(self.markups.apply(None, raw_bidask));
+
+        for trader_group in self.markups.get_trader_groups() {
+            let bidask = self.markups.apply(Some(trader_group), raw_bidask);
+
+            if let Some(bidasks) = self.client_items_by_groups.get_mut(trader_group) {
+                bidasks.insert_or_replace(bidask);
+            } else {
+                let mut bidasks = SortedVec::new();
+                bidasks.insert_or_replace(bidask);
+                self.client_items_by_groups.insert(trader_group.to_string(), bidasks);
+            }
         }
     }
 
+    /// Returns raw quote
     pub fn get(&self, instrument: &InstrumentSymbol) -> Option<&BidAsk> {
         self.items.get(instrument)
     }
 
-    pub fn find(&self, base_asset: &str, assets: &[&str]) -> SortedVec<InstrumentSymbol, BidAsk> {
+    /// Returns quote marked-up by profiles of trader group or raw quote if no markups are set
+    pub fn get_client(&self, trader_group: Option<&str>, instrument: &InstrumentSymbol) -> Option<&BidAsk> {
+        if self.markups.is_empty() {
+            return self.items.get(instrument);
+        }
+
+        // group without own profiles gets quotes of default profiles
+        let group_bidasks = trader_group
+            .and_then(|group| self.client_items_by_groups.get(group))
+            .unwrap_or(&self.default_client_items);
+
+        group_bidasks.get(instrument)
+    }
+
+    pub fn find(
+        &self,
+        instruments: &InstrumentsRegistry,
+        base_asset: &str,
+        assets: &[&str],
+    ) -> SortedVec<InstrumentSymbol, BidAsk> {
         let mut bidasks = SortedVec::new_with_capacity(assets.len());
         let base_asset: AssetSymbol = base_asset.into();
 
         for asset in assets.iter() {
             let asset: AssetSymbol = (*asset).into();
 
-            let instrument = BidAsk::get_instrument_symbol(&asset, &base_asset);
-            let bidask = self.items.get(&instrument);
+            let Some(instrument) = instruments.find_pair(&asset, &base_asset) else {
+                continue;
+            };
+            let bidask = self.items.get(&instrument.symbol);
 
             if let Some(bidask) = bidask {
                 bidasks.insert_or_replace(bidask.to_owned());
@@ -64,27 +190,197 @@ impl BidAsksCache {
         bidasks
     }
 
-    pub fn find_prices(&self, to_asset: &AssetSymbol, from_assets: &[&AssetSymbol]) -> SortedVec<AssetSymbol, AssetPrice> {
+    /// Assets without valid price are skipped
+    pub fn find_prices(
+        &self,
+        instruments: &InstrumentsRegistry,
+        to_asset: &AssetSymbol,
+        from_assets: &[&AssetSymbol],
+    ) -> SortedVec<AssetSymbol, AssetPrice> {
+        let mut prices = SortedVec::new_with_capacity(from_assets.len());
+
+        for item in self.find_prices_with_sources(instruments, to_asset, from_assets).iter() {
+            prices.insert_or_replace(item.price.clone());
+        }
+
+        prices
+    }
+
+    pub fn try_find_prices(
+        &self,
+        instruments: &InstrumentsRegistry,
+        to_asset: &AssetSymbol,
+        from_assets: &[&AssetSymbol],
+    ) -> Result<SortedVec<AssetSymbol, AssetPrice>, TradingError> {
+        let mut prices = SortedVec::new_with_capacity(from_assets.len());
+
+        for item in self.try_find_prices_with_sources(instruments, to_asset, from_assets)?.iter() {
+            prices.insert_or_replace(item.price.clone());
+        }
+
+        Ok(prices)
+    }
+
+    /// Finds prices of assets in to_asset by raw quotes directly, by inverted pairs or through one intermediate asset.
+    /// Assets without quotes or with zero or not finite price of inverted pair are skipped
+    pub fn find_prices_with_sources(
+        &self,
+        instruments: &InstrumentsRegistry,
+        to_asset: &AssetSymbol,
+        from_assets: &[&AssetSymbol],
+    ) -> SortedVec<AssetSymbol, SourcedAssetPrice> {
         let mut prices = SortedVec::new_with_capacity(from_assets.len());
 
         for asset in from_assets {
             let symbol = *asset;
 
-            if *asset == to_asset {
-                prices.insert_or_replace(AssetPrice {price: 1.0, symbol: symbol.clone()});
-                continue;
+            if let Ok(Some((price, source))) = self.find_price(instruments, symbol, to_asset) {
+                prices.insert_or_replace(SourcedAssetPrice {
+                    price: AssetPrice {price, symbol: symbol.clone()},
+                    source,
+                });
             }
+        }
+
+        prices
+    }
 
-            let instrument = BidAsk::get_instrument_symbol(asset, to_asset);
-            let bidask = self.items.get(&instrument);
+    /// Assets without quotes are skipped. Quote with zero or not finite price of inverted pair is an error
+    pub fn try_find_prices_with_sources(
+        &self,
+        instruments: &InstrumentsRegistry,
+        to_asset: &AssetSymbol,
+        from_assets: &[&AssetSymbol],
+    ) -> Result<SortedVec<AssetSymbol, SourcedAssetPrice>, TradingError> {
+        let mut prices = SortedVec::new_with_capacity(from_assets.len());
 
-            if let Some(bidask) = bidask {
-                let price = bidask.get_asset_price(asset, &crate::orders::OrderSide::Sell);
-                prices.insert_or_replace(AssetPrice {price, symbol: symbol.clone()});
+        for asset in from_assets {
+            let symbol = *asset;
+
+            if let Some((price, source)) = self.find_price(instruments, symbol, to_asset)? {
+                prices.insert_or_replace(SourcedAssetPrice {
+                    price: AssetPrice {price, symbol: symbol.clone()},
+                    source,
+                });
             }
         }
 
-        prices
+        Ok(prices)
+    }
+
+    fn find_price(
+        &self,
+        instruments: &InstrumentsRegistry,
+        from_asset: &AssetSymbol,
+        to_asset: &AssetSymbol,
+    ) -> Result<Option<(f64, AssetPriceSource)>, TradingError> {
+        if from_asset == to_asset {
+            return Ok(Some((1.0, AssetPriceSource::Same)));
+        }
+
+        if let Some((price, leg)) = self.find_pair_price(instruments, from_asset, to_asset)? {
+            return Ok(Some((price, AssetPriceSource::Pair(leg))));
+        }
+
+        for instrument in instruments.find_by_asset(from_asset) {
+            let Some(intermediate_asset) = instrument.get_counter_asset(from_asset) else {
+                continue;
+            };
+
+            if intermediate_asset == to_asset {
+                continue;
+            }
+
+            let Some(first_price) = self.get_leg_price(instruments, instrument, from_asset)? else {
+                continue;
+            };
+
+            let Some((second_price, second_leg)) = self.find_pair_price(instruments, intermediate_asset, to_asset)? else {
+                continue;
+            };
+
+            let first_leg = PriceLeg {
+                instrument: instrument.symbol.clone(),
+                is_inverted: instrument.is_inverted_for(from_asset),
+            };
+            let source = AssetPriceSource::Cross {
+                intermediate_asset: intermediate_asset.clone(),
+                legs: [first_leg, second_leg],
+            };
+
+            return Ok(Some((first_price * second_price, source)));
+        }
+
+        Ok(None)
+    }
+
+    fn find_pair_price(
+        &self,
+        instruments: &InstrumentsRegistry,
+        from_asset: &AssetSymbol,
+        to_asset: &AssetSymbol,
+    ) -> Result<Option<(f64, PriceLeg)>, TradingError> {
+        let Some(instrument) = instruments.find_pair(from_asset, to_asset) else {
+            return Ok(None);
+        };
+        let Some(price) = self.get_leg_price(instruments, instrument, from_asset)? else {
+            return Ok(None);
+        };
+        let leg = PriceLeg {
+            instrument: instrument.symbol.clone(),
+            is_inverted: instrument.is_inverted_for(from_asset),
+        };
+
+        Ok(Some((price, leg)))
+    }
+
+    fn get_leg_price(
+        &self,
+        instruments: &InstrumentsRegistry,
+        instrument: &Instrument,
+        from_asset: &AssetSymbol,
+    ) -> Result<Option<f64>, TradingError> {
+        let Some(bidask) = self.items.get(&instrument.symbol) else {
+            return Ok(None);
+        };
+
+        bidask
+            .try_get_asset_price(instruments, from_asset, &crate::orders::OrderSide::Sell)
+            .map(Some)
+    }
+}
+
+/// Instrument used to convert one asset to another
+#[derive(Clone, Debug, PartialEq)]
+pub struct PriceLeg {
+    pub instrument: InstrumentSymbol,
+    /// Asset is the quote asset of instrument so price was inverted
+    pub is_inverted: bool,
+}
+
+/// Describes how asset price was resolved
+#[derive(Clone, Debug, PartialEq)]
+pub enum AssetPriceSource {
+    /// Asset is the same as target asset
+    Same,
+    /// Price of direct or inverted instrument between asset and target asset
+    Pair(PriceLeg),
+    /// Price derived through intermediate asset
+    Cross {
+        intermediate_asset: AssetSymbol,
+        legs: [PriceLeg; 2],
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct SourcedAssetPrice {
+    pub price: AssetPrice,
+    pub source: AssetPriceSource,
+}
+
+impl EntityWithKey<AssetSymbol> for SourcedAssetPrice {
+    fn get_key(&self) -> &AssetSymbol {
+        &self.price.symbol
     }
 }
 
@@ -143,10 +439,18 @@ impl PositionsCache {
         self.ids_by_wallet_ids.contains_key(wallet_id)
     }
 
+    pub fn get(&self, id: &PositionId) -> Option<&Position> {
+        self.positions_by_ids.get(id)
+    }
+
     pub fn get_mut(&mut self, id: &PositionId) -> Option<&mut Position> {
         self.positions_by_ids.get_mut(id)
     }
 
+    pub fn iter(&self) -> impl Iterator<Item = &Position> {
+        self.positions_by_ids.values()
+    }
+
     pub fn remove(&mut self, position_id: &PositionId) -> Option<Position> {
         let position = self.positions_by_ids.remove(position_id);
 
@@ -163,14 +467,21 @@ impl PositionsCache {
 #[cfg(test)]
 mod tests {
     use rust_extensions::date_time::DateTimeAsMicroseconds;
-    use super::{PositionsCache};
+    use super::{AssetPriceSource, BidAsksCache, PositionsCache};
     use crate::{
-        orders::Order,
+        orders::{OpenContext, Order, OrderType, TimeInForce},
         positions::{BidAsk, Position},
     };
     use rust_extensions::sorted_vec::SortedVec;
     use uuid::Uuid;
     use crate::assets::{AssetAmount, AssetPrice};
+    use crate::asset_symbol::AssetSymbol;
+    use crate::clock::SystemClock;
+    use crate::commissions::CommissionSchedules;
+    use crate::errors::TradingError;
+    use crate::markups::{MarkupProfile, MarkupProfiles};
+    use crate::instruments::{Instrument, InstrumentsRegistry};
+    use crate::quote_filters::{QuoteFilter, QuoteFilterSettings};
     use crate::wallet_id::WalletId;
 
     #[test]
@@ -238,6 +549,108 @@ mod tests {
         assert_eq!(positions.len(), limit);
     }
 
+    #[test]
+    fn bidasks_cache_keeps_history_of_accepted_quotes() {
+        let mut cache = BidAsksCache::new(vec![]);
+        cache.set_history_capacity(Some(2));
+        cache.set_filter(Some(QuoteFilter::new(QuoteFilterSettings::default())));
+
+        cache.update(BidAsk::new_synthetic("BTCUSDT".into(), 1.0, 1.1));
+        cache.update(BidAsk::new_synthetic("BTCUSDT".into(), 0.0, 1.1));
+        cache.update(BidAsk::new_synthetic("BTCUSDT".into(), 1.1, 1.2));
+        cache.update(BidAsk::new_synthetic("BTCUSDT".into(), 1.2, 1.3));
+
+        let history = cache.get_history(&"BTCUSDT".into()).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history.get_last().unwrap().bid, 1.2);
+    }
+
+    #[test]
+    fn bidasks_cache_keeps_raw_and_client_quotes() {
+        let mut cache = BidAsksCache::new(vec![]);
+        let mut markups = MarkupProfiles::new();
+        markups.add(None, new_markup_profile(10.0));
+        markups.add(Some("vip"), new_markup_profile(1.0));
+        cache.set_markups(markups);
+
+        cache.update(BidAsk::new_synthetic("BTCUSDT".into(), 100.0, 101.0));
+
+        let instrument = "BTCUSDT".into();
+        assert_eq!(cache.get(&instrument).unwrap().bid, 100.0);
+        assert_eq!(cache.get_client(Some("vip"), &instrument).unwrap().bid, 99.0);
+        assert_eq!(cache.get_client(Some("other"), &instrument).unwrap().bid, 90.0);
+        assert_eq!(cache.get_client(None, &instrument).unwrap().ask, 111.0);
+    }
+
+    #[test]
+    fn find_prices_direct_and_inverted() {
+        let instruments = InstrumentsRegistry::new(vec![
+            Instrument::new("ETHUSDT", "ETH", "USDT"),
+            Instrument::new("USDTBTC", "USDT", "BTC"),
+        ]);
+        let cache = BidAsksCache::new(vec![
+            BidAsk::new_synthetic("ETHUSDT".into(), 1999.0, 2000.0),
+            BidAsk::new_synthetic("USDTBTC".into(), 0.00002, 0.000025),
+        ]);
+        let eth: AssetSymbol = "ETH".into();
+        let btc: AssetSymbol = "BTC".into();
+
+        let prices = cache.find_prices_with_sources(&instruments, &"USDT".into(), &[&eth, &btc]);
+
+        let eth_price = prices.get(&eth).unwrap();
+        assert_eq!(eth_price.price.price, 2000.0);
+        assert!(matches!(&eth_price.source, AssetPriceSource::Pair(leg) if !leg.is_inverted));
+        let btc_price = prices.get(&btc).unwrap();
+        assert_eq!(btc_price.price.price, 1.0 / 0.00002);
+        assert!(matches!(&btc_price.source, AssetPriceSource::Pair(leg) if leg.is_inverted));
+    }
+
+    #[test]
+    fn find_prices_cross() {
+        let instruments = InstrumentsRegistry::new(vec![
+            Instrument::new("EURUSDT", "EUR", "USDT"),
+            Instrument::new("BTCUSDT", "BTC", "USDT"),
+        ]);
+        let cache = BidAsksCache::new(vec![
+            BidAsk::new_synthetic("EURUSDT".into(), 1.0, 1.1),
+            BidAsk::new_synthetic("BTCUSDT".into(), 55000.0, 55000.0),
+        ]);
+        let eur: AssetSymbol = "EUR".into();
+
+        let prices = cache.find_prices_with_sources(&instruments, &"BTC".into(), &[&eur]);
+
+        let eur_price = prices.get(&eur).unwrap();
+        assert_eq!(eur_price.price.price, 1.1 / 55000.0);
+        let AssetPriceSource::Cross { intermediate_asset, legs } = &eur_price.source else {
+            panic!("must be cross price");
+        };
+        assert_eq!(intermediate_asset, &AssetSymbol::from("USDT"));
+        assert!(!legs[0].is_inverted);
+        assert!(legs[1].is_inverted);
+    }
+
+    #[test]
+    fn find_prices_with_zero_inverted_price() {
+        let instruments = InstrumentsRegistry::new(vec![Instrument::new("USDTBTC", "USDT", "BTC")]);
+        let cache = BidAsksCache::new(vec![BidAsk::new_synthetic("USDTBTC".into(), 0.0, 0.000025)]);
+        let btc: AssetSymbol = "BTC".into();
+
+        let result = cache.try_find_prices(&instruments, &"USDT".into(), &[&btc]);
+
+        assert!(matches!(result, Err(TradingError::MissingPrice(_))));
+        assert!(cache.find_prices(&instruments, &"USDT".into(), &[&btc]).is_empty());
+    }
+
+    fn new_markup_profile(fixed_points: f64) -> MarkupProfile {
+        MarkupProfile {
+            instrument: "BTCUSDT".into(),
+            fixed_points,
+            point_size: 1.0,
+            percent: 0.0,
+            min_spread: 0.0,
+        }
+    }
+
     fn new_position() -> Position {
         let mut invest_assets = SortedVec::new();
         invest_assets.insert_or_replace(AssetAmount {amount: 100.0, symbol: "BTC".into()});
@@ -248,7 +661,10 @@ mod tests {
             trader_id: "test".to_string(),
             wallet_id: Uuid::new_v4().into(),
             created_date: DateTimeAsMicroseconds::now(),
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::GoodTillCanceled,
             desire_price: None,
+            stop_price: None,
             funding_fee_period: None,
             invest_assets,
             leverage: 1.0,
@@ -259,6 +675,8 @@ mod tests {
             margin_call_percent: 10.0,
             top_up_enabled: false,
             top_up_percent: 10.0,
+            trader_group: None,
+            reduce_only: false,
         };
         let mut prices = SortedVec::new();
         prices.insert_or_replace(AssetPrice {price: 22300.0, symbol: "BTC".into()});
@@ -269,7 +687,9 @@ mod tests {
             instrument: "ATOMUSDT".into(),
         };
 
-        order.open(&bidask, &prices)
+        let instruments = InstrumentsRegistry::new(vec![Instrument::new("ATOMUSDT", "ATOM", "USDT")]);
+
+        order.open(&bidask, &prices, &OpenContext::new(&instruments, &CommissionSchedules::new(), &SystemClock))
     }
 
     fn new_position_with_wallet(wallet_id: &WalletId) -> Position {
@@ -282,7 +702,10 @@ mod tests {
             trader_id: "test".to_string(),
             wallet_id: wallet_id.to_owned(),
             created_date: DateTimeAsMicroseconds::now(),
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::GoodTillCanceled,
             desire_price: None,
+            stop_price: None,
             funding_fee_period: None,
             invest_assets,
             leverage: 1.0,
@@ -293,6 +716,8 @@ mod tests {
             margin_call_percent: 10.0,
             top_up_enabled: false,
             top_up_percent: 10.0,
+            trader_group: None,
+            reduce_only: false,
         };
         let mut prices = SortedVec::new();
         prices.insert_or_replace(AssetPrice {price: 22300.0, symbol: "BTC".into()});
@@ -303,6 +728,8 @@ mod tests {
             instrument: "ATOMUSDT".into(),
         };
 
-        order.open(&bidask, &prices)
+        let instruments = InstrumentsRegistry::new(vec![Instrument::new("ATOMUSDT", "ATOM", "USDT")]);
+
+        order.open(&bidask, &prices, &OpenContext::new(&instruments, &CommissionSchedules::new(), &SystemClock))
     }
 }
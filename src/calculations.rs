@@ -3,17 +3,26 @@ use std::collections::HashMap;
 use rust_extensions::sorted_vec::SortedVec;
 use crate::asset_symbol::AssetSymbol;
 use crate::assets::{AssetAmount, AssetPrice};
+use crate::errors::TradingError;
 
 pub fn get_close_price(
     bidasks: &HashMap<String, BidAsk>,
     instrument: &str,
     side: &OrderSide,
 ) -> f64 {
-    let bidask = bidasks
-        .get(instrument)
-        .unwrap_or_else(|| panic!("BidAsk not found for {}", instrument));
+    try_get_close_price(bidasks, instrument, side).unwrap_or_else(|err| panic!("{}", err))
+}
 
-    bidask.get_close_price(side)
+pub fn try_get_close_price(
+    bidasks: &HashMap<String, BidAsk>,
+    instrument: &str,
+    side: &OrderSide,
+) -> Result<f64, TradingError> {
+    let Some(bidask) = bidasks.get(instrument) else {
+        return Err(TradingError::MissingPrice(instrument.to_string()));
+    };
+
+    Ok(bidask.get_close_price(side))
 }
 
 pub fn get_open_price(
@@ -21,11 +30,19 @@ pub fn get_open_price(
     instrument: &str,
     side: &OrderSide,
 ) -> f64 {
-    let bidask = bidasks
-        .get(instrument)
-        .unwrap_or_else(|| panic!("BidAsk not found for {}", instrument));
+    try_get_open_price(bidasks, instrument, side).unwrap_or_else(|err| panic!("{}", err))
+}
+
+pub fn try_get_open_price(
+    bidasks: &HashMap<String, BidAsk>,
+    instrument: &str,
+    side: &OrderSide,
+) -> Result<f64, TradingError> {
+    let Some(bidask) = bidasks.get(instrument) else {
+        return Err(TradingError::MissingPrice(instrument.to_string()));
+    };
 
-    bidask.get_open_price(side)
+    Ok(bidask.get_open_price(side))
 }
 
 pub fn calculate_margin_percent(invest_amount: f64, pnl: f64) -> f64 {
@@ -42,17 +59,24 @@ pub fn calculate_total_amount(
     asset_amounts: &SortedVec<AssetSymbol, AssetAmount>,
     asset_prices: &SortedVec<AssetSymbol, AssetPrice>,
 ) -> f64 {
+    try_calculate_total_amount(asset_amounts, asset_prices).unwrap_or_else(|err| panic!("{}", err))
+}
+
+pub fn try_calculate_total_amount(
+    asset_amounts: &SortedVec<AssetSymbol, AssetAmount>,
+    asset_prices: &SortedVec<AssetSymbol, AssetPrice>,
+) -> Result<f64, TradingError> {
     let mut total_amount = 0.0;
 
     for item in asset_amounts.iter() {
-        let price = asset_prices
-            .get(&item.symbol)
-            .unwrap_or_else(|| panic!("Price not found for {}", item.symbol));
+        let Some(price) = asset_prices.get(&item.symbol) else {
+            return Err(TradingError::MissingPrice(item.symbol.to_string()));
+        };
         let estimated_amount = price.price * item.amount;
         total_amount += estimated_amount;
     }
 
-    total_amount
+    Ok(total_amount)
 }
 
 pub fn ceil(x: f64, precision: u32) -> f64 {
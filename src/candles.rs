@@ -0,0 +1,274 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+use ahash::AHashMap;
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+use rust_extensions::date_time::DateTimeAsMicroseconds;
+use crate::instrument_symbol::InstrumentSymbol;
+use crate::positions::BidAsk;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, IntoPrimitive, TryFromPrimitive)]
+#[repr(i32)]
+pub enum CandleType {
+    Minute = 0,
+    Hour = 1,
+    Day = 2,
+}
+
+impl CandleType {
+    pub const ALL: [CandleType; 3] = [CandleType::Minute, CandleType::Hour, CandleType::Day];
+
+    pub fn get_duration(&self) -> Duration {
+        match self {
+            CandleType::Minute => Duration::from_secs(60),
+            CandleType::Hour => Duration::from_secs(60 * 60),
+            CandleType::Day => Duration::from_secs(60 * 60 * 24),
+        }
+    }
+
+    /// Returns start date of candle containing datetime. Days start at UTC midnight
+    pub fn get_start_date(&self, datetime: DateTimeAsMicroseconds) -> DateTimeAsMicroseconds {
+        let duration = self.get_duration().as_micros() as i64;
+        let start = datetime.unix_microseconds - datetime.unix_microseconds.rem_euclid(duration);
+
+        DateTimeAsMicroseconds::new(start)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CandleData {
+    pub open: f64,
+    pub close: f64,
+    pub high: f64,
+    pub low: f64,
+}
+
+impl CandleData {
+    pub fn new(price: f64) -> Self {
+        Self {
+            open: price,
+            close: price,
+            high: price,
+            low: price,
+        }
+    }
+
+    pub fn update(&mut self, price: f64) {
+        self.close = price;
+        self.update_extremes(price);
+    }
+
+    /// Updates open price by quote older than the first quote of candle
+    pub fn update_open(&mut self, price: f64) {
+        self.open = price;
+        self.update_extremes(price);
+    }
+
+    pub fn update_extremes(&mut self, price: f64) {
+        if price > self.high {
+            self.high = price;
+        }
+
+        if price < self.low {
+            self.low = price;
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BidAskCandle {
+    pub instrument: InstrumentSymbol,
+    pub candle_type: CandleType,
+    /// Start date of candle period
+    pub datetime: DateTimeAsMicroseconds,
+    /// Date of quote of open prices
+    pub open_date: DateTimeAsMicroseconds,
+    /// Date of quote of close prices
+    pub close_date: DateTimeAsMicroseconds,
+    pub bid: CandleData,
+    pub ask: CandleData,
+}
+
+impl BidAskCandle {
+    pub fn new(candle_type: CandleType, bidask: &BidAsk) -> Self {
+        Self {
+            instrument: bidask.instrument.clone(),
+            candle_type,
+            datetime: candle_type.get_start_date(bidask.datetime),
+            open_date: bidask.datetime,
+            close_date: bidask.datetime,
+            bid: CandleData::new(bidask.bid),
+            ask: CandleData::new(bidask.ask),
+        }
+    }
+
+    /// Quotes received out of order change close or open prices only if they are the latest or the earliest
+    pub fn update(&mut self, bidask: &BidAsk) {
+        if bidask.datetime.unix_microseconds >= self.close_date.unix_microseconds {
+            self.close_date = bidask.datetime;
+            self.bid.update(bidask.bid);
+            self.ask.update(bidask.ask);
+        } else if bidask.datetime.unix_microseconds < self.open_date.unix_microseconds {
+            self.open_date = bidask.datetime;
+            self.bid.update_open(bidask.bid);
+            self.ask.update_open(bidask.ask);
+        } else {
+            self.bid.update_extremes(bidask.bid);
+            self.ask.update_extremes(bidask.ask);
+        }
+    }
+}
+
+/// Aggregates quotes into bid and ask candles of all types by instruments
+#[derive(Debug, Clone)]
+pub struct CandlesCache {
+    /// Max count of candles kept by instrument and candle type. The oldest candles are removed first
+    max_count: usize,
+    items: AHashMap<(InstrumentSymbol, CandleType), BTreeMap<i64, BidAskCandle>>,
+}
+
+impl CandlesCache {
+    pub fn new(max_count: usize) -> Self {
+        Self {
+            max_count,
+            items: AHashMap::new(),
+        }
+    }
+
+    /// Updates candles of all types containing quote date. New period starts a new candle
+    pub fn update(&mut self, bidask: &BidAsk) {
+        for candle_type in CandleType::ALL {
+            let candles = self
+                .items
+                .entry((bidask.instrument.clone(), candle_type))
+                .or_default();
+            let start_date = candle_type.get_start_date(bidask.datetime);
+
+            if let Some(candle) = candles.get_mut(&start_date.unix_microseconds) {
+                candle.update(bidask);
+                continue;
+            }
+
+            candles.insert(start_date.unix_microseconds, BidAskCandle::new(candle_type, bidask));
+
+            while candles.len() > self.max_count {
+                candles.pop_first();
+            }
+        }
+    }
+
+    /// Returns candles with start dates in range including both bounds
+    pub fn get(
+        &self,
+        instrument: &InstrumentSymbol,
+        candle_type: CandleType,
+        date_from: DateTimeAsMicroseconds,
+        date_to: DateTimeAsMicroseconds,
+    ) -> Vec<&BidAskCandle> {
+        let Some(candles) = self.items.get(&(instrument.clone(), candle_type)) else {
+            return Vec::with_capacity(0);
+        };
+
+        if date_from.unix_microseconds > date_to.unix_microseconds {
+            return Vec::with_capacity(0);
+        }
+
+        candles
+            .range(date_from.unix_microseconds..=date_to.unix_microseconds)
+            .map(|(_, candle)| candle)
+            .collect()
+    }
+
+    pub fn get_last(&self, instrument: &InstrumentSymbol, candle_type: CandleType) -> Option<&BidAskCandle> {
+        self.items
+            .get(&(instrument.clone(), candle_type))
+            .and_then(|candles| candles.last_key_value())
+            .map(|(_, candle)| candle)
+    }
+
+    pub fn remove_instrument(&mut self, instrument: &InstrumentSymbol) {
+        for candle_type in CandleType::ALL {
+            self.items.remove(&(instrument.clone(), candle_type));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CandleType, CandlesCache};
+    use crate::positions::BidAsk;
+    use rust_extensions::date_time::DateTimeAsMicroseconds;
+
+    const MINUTE: i64 = 60_000_000;
+
+    #[test]
+    fn aggregate_and_rollover() {
+        let mut cache = CandlesCache::new(100);
+        cache.update(&new_bidask(10.0, 0));
+        cache.update(&new_bidask(12.0, MINUTE / 2));
+        cache.update(&new_bidask(9.0, MINUTE / 2 + 1));
+        cache.update(&new_bidask(11.0, MINUTE));
+
+        let candles = cache.get(
+            &"BTCUSDT".into(),
+            CandleType::Minute,
+            DateTimeAsMicroseconds::new(0),
+            DateTimeAsMicroseconds::new(MINUTE),
+        );
+
+        assert_eq!(candles.len(), 2);
+        assert_eq!(candles[0].bid.open, 10.0);
+        assert_eq!(candles[0].bid.high, 12.0);
+        assert_eq!(candles[0].bid.low, 9.0);
+        assert_eq!(candles[0].bid.close, 9.0);
+        assert_eq!(candles[1].datetime.unix_microseconds, MINUTE);
+        assert_eq!(candles[1].ask.open, 12.0);
+
+        let hour_candle = cache.get_last(&"BTCUSDT".into(), CandleType::Hour).unwrap();
+        assert_eq!(hour_candle.bid.close, 11.0);
+        assert_eq!(hour_candle.bid.high, 12.0);
+    }
+
+    #[test]
+    fn out_of_order_quote_keeps_close() {
+        let mut cache = CandlesCache::new(100);
+        cache.update(&new_bidask(10.0, MINUTE / 2));
+        cache.update(&new_bidask(11.0, MINUTE / 2 + 10));
+        cache.update(&new_bidask(8.0, MINUTE / 2 + 5));
+        cache.update(&new_bidask(9.0, MINUTE / 4));
+
+        let candle = cache.get_last(&"BTCUSDT".into(), CandleType::Minute).unwrap();
+
+        assert_eq!(candle.bid.open, 9.0);
+        assert_eq!(candle.bid.close, 11.0);
+        assert_eq!(candle.bid.low, 8.0);
+        assert_eq!(candle.close_date.unix_microseconds, MINUTE / 2 + 10);
+    }
+
+    #[test]
+    fn remove_oldest_over_max_count() {
+        let mut cache = CandlesCache::new(2);
+
+        for i in 0..3 {
+            cache.update(&new_bidask(10.0, i * MINUTE));
+        }
+
+        let candles = cache.get(
+            &"BTCUSDT".into(),
+            CandleType::Minute,
+            DateTimeAsMicroseconds::new(0),
+            DateTimeAsMicroseconds::new(10 * MINUTE),
+        );
+
+        assert_eq!(candles.len(), 2);
+        assert_eq!(candles[0].datetime.unix_microseconds, MINUTE);
+    }
+
+    fn new_bidask(bid: f64, unix_microseconds: i64) -> BidAsk {
+        BidAsk {
+            instrument: "BTCUSDT".into(),
+            datetime: DateTimeAsMicroseconds::new(unix_microseconds),
+            bid,
+            ask: bid + 1.0,
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::Duration;
+use rust_extensions::date_time::DateTimeAsMicroseconds;
+use crate::positions::BidAsk;
+
+/// Source of current time for positions and monitor
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTimeAsMicroseconds;
+
+    /// Called by monitor for every quote before positions are updated
+    fn on_quote(&self, _bidask: &BidAsk) {}
+}
+
+/// Current system time
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTimeAsMicroseconds {
+        DateTimeAsMicroseconds::now()
+    }
+}
+
+/// Time set explicitly. Used by tests
+#[derive(Debug)]
+pub struct ManualClock {
+    now: AtomicI64,
+}
+
+impl ManualClock {
+    pub fn new(now: DateTimeAsMicroseconds) -> Self {
+        Self {
+            now: AtomicI64::new(now.unix_microseconds),
+        }
+    }
+
+    pub fn set(&self, now: DateTimeAsMicroseconds) {
+        self.now.store(now.unix_microseconds, Ordering::SeqCst);
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        self.now.fetch_add(duration.as_micros() as i64, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTimeAsMicroseconds {
+        DateTimeAsMicroseconds::new(self.now.load(Ordering::SeqCst))
+    }
+}
+
+/// Time of the latest quote. Used to replay quotes in backtests
+#[derive(Debug)]
+pub struct QuoteClock {
+    now: AtomicI64,
+}
+
+impl QuoteClock {
+    pub fn new(start: DateTimeAsMicroseconds) -> Self {
+        Self {
+            now: AtomicI64::new(start.unix_microseconds),
+        }
+    }
+}
+
+impl Clock for QuoteClock {
+    fn now(&self) -> DateTimeAsMicroseconds {
+        DateTimeAsMicroseconds::new(self.now.load(Ordering::SeqCst))
+    }
+
+    /// Moves time forward to quote date. Older quotes don't move time back
+    fn on_quote(&self, bidask: &BidAsk) {
+        self.now.fetch_max(bidask.datetime.unix_microseconds, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Clock, ManualClock, QuoteClock};
+    use crate::positions::BidAsk;
+    use rust_extensions::date_time::DateTimeAsMicroseconds;
+    use std::time::Duration;
+
+    #[test]
+    fn advance_manual_and_quote_clocks() {
+        let clock = ManualClock::new(DateTimeAsMicroseconds::new(1_000_000));
+        clock.advance(Duration::from_secs(2));
+
+        assert_eq!(clock.now().unix_microseconds, 3_000_000);
+
+        let clock = QuoteClock::new(DateTimeAsMicroseconds::new(0));
+        let mut bidask = BidAsk::new_synthetic("BTCUSDT".into(), 10.0, 11.0);
+        bidask.datetime = DateTimeAsMicroseconds::new(500);
+        clock.on_quote(&bidask);
+        bidask.datetime = DateTimeAsMicroseconds::new(200);
+        clock.on_quote(&bidask);
+
+        assert_eq!(clock.now().unix_microseconds, 500);
+    }
+}
@@ -0,0 +1,177 @@
+use ahash::AHashMap;
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+use rust_extensions::date_time::DateTimeAsMicroseconds;
+use rust_extensions::sorted_vec::{EntityWithKey, SortedVec};
+use crate::asset_symbol::AssetSymbol;
+use crate::assets::AssetAmount;
+use crate::instrument_symbol::InstrumentSymbol;
+
+/// Trading fees of instrument charged on open and on close
+#[derive(Clone, Debug, PartialEq)]
+pub struct CommissionSchedule {
+    pub instrument: InstrumentSymbol,
+    /// Percent of volume
+    pub volume_percent: f64,
+    /// Fixed amount in base asset for every lot of volume
+    pub per_lot: f64,
+    /// Volume in base asset of one lot
+    pub lot_volume: f64,
+}
+
+impl CommissionSchedule {
+    /// Returns commission share of volume
+    pub fn get_rate(&self) -> f64 {
+        // lots of asset volume are converted back to asset by the same price, so price isn't needed
+        let per_lot_rate = if self.lot_volume > 0.0 {
+            self.per_lot / self.lot_volume
+        } else {
+            0.0
+        };
+
+        self.volume_percent / 100.0 + per_lot_rate
+    }
+
+    /// Calculates commission in invested assets for volume of amounts with leverage
+    pub fn calculate(
+        &self,
+        invest_assets: &SortedVec<AssetSymbol, AssetAmount>,
+        leverage: f64,
+    ) -> SortedVec<AssetSymbol, AssetAmount> {
+        let rate = self.get_rate();
+        let mut amounts = SortedVec::new_with_capacity(invest_assets.len());
+
+        for item in invest_assets.iter() {
+            amounts.insert_or_replace(AssetAmount {
+                symbol: item.symbol.clone(),
+                amount: item.amount * leverage * rate,
+            });
+        }
+
+        amounts
+    }
+}
+
+impl EntityWithKey<InstrumentSymbol> for CommissionSchedule {
+    fn get_key(&self) -> &InstrumentSymbol {
+        &self.instrument
+    }
+}
+
+/// Commission schedules by instruments with overrides by trader groups
+#[derive(Clone, Debug)]
+pub struct CommissionSchedules {
+    default_schedules: SortedVec<InstrumentSymbol, CommissionSchedule>,
+    schedules_by_groups: AHashMap<String, SortedVec<InstrumentSymbol, CommissionSchedule>>,
+}
+
+impl CommissionSchedules {
+    pub fn new() -> Self {
+        Self {
+            default_schedules: SortedVec::new(),
+            schedules_by_groups: AHashMap::new(),
+        }
+    }
+
+    /// Adds schedule for trader group or default schedule if group is None
+    pub fn add(&mut self, trader_group: Option<&str>, schedule: CommissionSchedule) {
+        let Some(trader_group) = trader_group else {
+            self.default_schedules.insert_or_replace(schedule);
+            return;
+        };
+
+        let schedules = self.schedules_by_groups.get_mut(trader_group);
+
+        if let Some(schedules) = schedules {
+            schedules.insert_or_replace(schedule);
+        } else {
+            let mut schedules = SortedVec::new();
+            schedules.insert_or_replace(schedule);
+            self.schedules_by_groups.insert(trader_group.to_string(), schedules);
+        }
+    }
+
+    pub fn remove(
+        &mut self,
+        trader_group: Option<&str>,
+        instrument: &InstrumentSymbol,
+    ) -> Option<CommissionSchedule> {
+        let Some(trader_group) = trader_group else {
+            return self.default_schedules.remove(instrument);
+        };
+
+        let schedules = self.schedules_by_groups.get_mut(trader_group)?;
+        let schedule = schedules.remove(instrument);
+
+        if schedules.is_empty() {
+            self.schedules_by_groups.remove(trader_group);
+        }
+
+        schedule
+    }
+
+    /// Returns schedule of trader group or default schedule if group has no own schedule for instrument
+    pub fn get(
+        &self,
+        trader_group: Option<&str>,
+        instrument: &InstrumentSymbol,
+    ) -> Option<&CommissionSchedule> {
+        let group_schedule = trader_group
+            .and_then(|group| self.schedules_by_groups.get(group))
+            .and_then(|schedules| schedules.get(instrument));
+
+        group_schedule.or_else(|| self.default_schedules.get(instrument))
+    }
+}
+
+impl Default for CommissionSchedules {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, IntoPrimitive, TryFromPrimitive)]
+#[repr(i32)]
+pub enum CommissionType {
+    Open = 0,
+    Close = 1,
+}
+
+/// Commission charged from position in invested assets
+#[derive(Debug, Clone)]
+pub struct PositionCommission {
+    pub commission_type: CommissionType,
+    pub date: DateTimeAsMicroseconds,
+    pub amounts: SortedVec<AssetSymbol, AssetAmount>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CommissionSchedule, CommissionSchedules};
+    use crate::assets::AssetAmount;
+    use rust_extensions::sorted_vec::SortedVec;
+
+    #[test]
+    fn calculate_group_commission() {
+        let mut schedules = CommissionSchedules::new();
+        schedules.add(None, new_schedule(0.1, 0.0));
+        schedules.add(Some("vip"), new_schedule(0.05, 1.0));
+        let mut invest_assets = SortedVec::new();
+        invest_assets.insert_or_replace(AssetAmount {amount: 100.0, symbol: "USDT".into()});
+
+        let schedule = schedules.get(Some("vip"), &"BTCUSDT".into()).unwrap();
+        let amounts = schedule.calculate(&invest_assets, 10.0);
+
+        // 1000 volume: 0.05% is 0.5 and 10 lots of 100 are 10.0
+        assert!((amounts.get(&"USDT".into()).unwrap().amount - 10.5).abs() < 1e-9);
+        assert_eq!(schedules.get(Some("other"), &"BTCUSDT".into()).unwrap().volume_percent, 0.1);
+    }
+
+    fn new_schedule(volume_percent: f64, per_lot: f64) -> CommissionSchedule {
+        CommissionSchedule {
+            instrument: "BTCUSDT".into(),
+            volume_percent,
+            per_lot,
+            lot_volume: 100.0,
+        }
+    }
+}
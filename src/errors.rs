@@ -0,0 +1,46 @@
+use std::fmt::Display;
+use crate::orders::OrderViolation;
+use crate::position_id::PositionId;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TradingError {
+    /// Price is not found for asset or instrument
+    MissingPrice(String),
+    /// Instrument is not registered or can't be used for the operation
+    InvalidInstrument(String),
+    /// Leverage is less or equals zero or is not a number
+    InvalidLeverage(f64),
+    /// Operation can't be done in current position state
+    PositionStateMismatch(String),
+    /// Amount is not a positive number or exceeds available amount
+    InvalidAmount(f64),
+    /// Order fields are inconsistent
+    InvalidOrder(Vec<OrderViolation>),
+    PositionNotFound(PositionId),
+    BalanceNotFound(String),
+}
+
+impl Display for TradingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TradingError::MissingPrice(symbol) => write!(f, "Price not found for {}", symbol),
+            TradingError::InvalidInstrument(message) => write!(f, "Invalid instrument: {}", message),
+            TradingError::InvalidLeverage(leverage) => write!(f, "Invalid leverage {}", leverage),
+            TradingError::PositionStateMismatch(message) => write!(f, "Invalid position state: {}", message),
+            TradingError::InvalidAmount(amount) => write!(f, "Invalid amount {}", amount),
+            TradingError::InvalidOrder(violations) => {
+                write!(f, "Invalid order:")?;
+
+                for violation in violations.iter() {
+                    write!(f, " {:?};", violation)?;
+                }
+
+                Ok(())
+            }
+            TradingError::PositionNotFound(id) => write!(f, "Position {} not found", id),
+            TradingError::BalanceNotFound(id) => write!(f, "Balance {} not found", id),
+        }
+    }
+}
+
+impl std::error::Error for TradingError {}
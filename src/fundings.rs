@@ -0,0 +1,48 @@
+use ahash::AHashMap;
+use crate::instrument_symbol::InstrumentSymbol;
+use crate::orders::OrderSide;
+
+/// Funding rates of instrument in percent of position volume charged every funding period.
+/// Positive rate is paid by position and negative rate is received
+#[derive(Debug, Clone, PartialEq)]
+pub struct FundingRate {
+    pub instrument: InstrumentSymbol,
+    pub buy_rate: f64,
+    pub sell_rate: f64,
+}
+
+impl FundingRate {
+    pub fn get_rate(&self, side: &OrderSide) -> f64 {
+        match side {
+            OrderSide::Buy => self.buy_rate,
+            OrderSide::Sell => self.sell_rate,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FundingRates {
+    items: AHashMap<InstrumentSymbol, FundingRate>,
+}
+
+impl FundingRates {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, rate: FundingRate) {
+        self.items.insert(rate.instrument.clone(), rate);
+    }
+
+    pub fn remove(&mut self, instrument: &InstrumentSymbol) -> Option<FundingRate> {
+        self.items.remove(instrument)
+    }
+
+    pub fn get(&self, instrument: &InstrumentSymbol) -> Option<&FundingRate> {
+        self.items.get(instrument)
+    }
+
+    pub fn get_rate(&self, instrument: &InstrumentSymbol, side: &OrderSide) -> Option<f64> {
+        self.items.get(instrument).map(|rate| rate.get_rate(side))
+    }
+}
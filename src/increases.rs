@@ -0,0 +1,14 @@
+use rust_extensions::date_time::DateTimeAsMicroseconds;
+use rust_extensions::sorted_vec::SortedVec;
+use crate::asset_symbol::AssetSymbol;
+use crate::assets::{AssetAmount, AssetPrice};
+
+/// Invest assets added to active position volume at instrument price
+#[derive(Debug, Clone)]
+pub struct PositionIncrease {
+    pub id: String,
+    pub date: DateTimeAsMicroseconds,
+    pub invest_assets: SortedVec<AssetSymbol, AssetAmount>,
+    pub instrument_price: f64,
+    pub asset_prices: SortedVec<AssetSymbol, AssetPrice>,
+}
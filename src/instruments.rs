@@ -0,0 +1,339 @@
+use ahash::AHashMap;
+use rust_extensions::sorted_vec::{EntityWithKey, SortedVec};
+use crate::asset_symbol::AssetSymbol;
+use crate::calculations::round;
+use crate::instrument_symbol::InstrumentSymbol;
+
+#[derive(Clone, Debug)]
+pub struct Instrument {
+    pub symbol: InstrumentSymbol,
+    pub base_asset: AssetSymbol,
+    pub quote_asset: AssetSymbol,
+    pub spec: Option<InstrumentSpec>,
+}
+
+/// Trading parameters of instrument
+#[derive(Clone, Debug, PartialEq)]
+pub struct InstrumentSpec {
+    /// Minimal price change. Prices are rounded to it
+    pub tick_size: f64,
+    /// Minimal volume change in base asset. Volumes are floored to it
+    pub lot_step: f64,
+    pub min_volume: f64,
+    pub max_volume: Option<f64>,
+    pub max_leverage: Option<f64>,
+    /// Decimals of pnl amounts. Overrides monitor pnl accuracy
+    pub pnl_accuracy: Option<u32>,
+}
+
+impl InstrumentSpec {
+    pub fn get_price_precision(&self) -> u32 {
+        get_step_precision(self.tick_size)
+    }
+
+    pub fn get_volume_precision(&self) -> u32 {
+        get_step_precision(self.lot_step)
+    }
+
+    /// Rounds price to the nearest tick
+    pub fn round_price(&self, price: f64) -> f64 {
+        if self.tick_size <= 0.0 {
+            return price;
+        }
+
+        round((price / self.tick_size).round() * self.tick_size, self.get_price_precision())
+    }
+
+    /// Floors volume to lot step
+    pub fn floor_volume(&self, volume: f64) -> f64 {
+        if self.lot_step <= 0.0 {
+            return volume;
+        }
+
+        // steps count is rounded first to skip float error of exact multiples
+        let steps = round(volume / self.lot_step, 9).floor();
+
+        round(steps * self.lot_step, self.get_volume_precision())
+    }
+
+    /// Rounds pnl amount to pnl accuracy
+    pub fn round_amount(&self, amount: f64) -> f64 {
+        match self.pnl_accuracy {
+            Some(pnl_accuracy) => round(amount, pnl_accuracy),
+            None => amount,
+        }
+    }
+
+    /// Checks volume in base asset against volume limits
+    pub fn is_volume_valid(&self, volume: f64) -> bool {
+        volume >= self.min_volume && self.max_volume.map(|max| volume <= max).unwrap_or(true)
+    }
+}
+
+/// Returns decimals count of step like 0.01 or 0.5
+fn get_step_precision(step: f64) -> u32 {
+    if step <= 0.0 || step >= 1.0 {
+        return 0;
+    }
+
+    let mut precision = 0;
+    let mut value = step;
+
+    while precision < 15 && (value - value.round()).abs() > 1e-9 {
+        value *= 10.0;
+        precision += 1;
+    }
+
+    precision
+}
+
+impl Instrument {
+    pub fn new(
+        symbol: impl Into<InstrumentSymbol>,
+        base_asset: impl Into<AssetSymbol>,
+        quote_asset: impl Into<AssetSymbol>,
+    ) -> Self {
+        Self {
+            symbol: symbol.into(),
+            base_asset: base_asset.into(),
+            quote_asset: quote_asset.into(),
+            spec: None,
+        }
+    }
+
+    pub fn with_spec(mut self, spec: InstrumentSpec) -> Self {
+        self.spec = Some(spec);
+
+        self
+    }
+
+    pub fn contains_asset(&self, asset: &AssetSymbol) -> bool {
+        self.base_asset == *asset || self.quote_asset == *asset
+    }
+
+    /// Returns the other asset of the pair if instrument contains asset
+    pub fn get_counter_asset(&self, asset: &AssetSymbol) -> Option<&AssetSymbol> {
+        if self.base_asset == *asset {
+            return Some(&self.quote_asset);
+        }
+
+        if self.quote_asset == *asset {
+            return Some(&self.base_asset);
+        }
+
+        None
+    }
+
+    /// Returns true if instrument is quoted in asset, so asset price must be inverted
+    pub fn is_inverted_for(&self, asset: &AssetSymbol) -> bool {
+        self.quote_asset == *asset
+    }
+}
+
+impl EntityWithKey<InstrumentSymbol> for Instrument {
+    fn get_key(&self) -> &InstrumentSymbol {
+        &self.symbol
+    }
+}
+
+/// Resolves instruments by their symbols and by (base asset, quote asset) pairs
+#[derive(Clone, Debug)]
+pub struct InstrumentsRegistry {
+    items: SortedVec<InstrumentSymbol, Instrument>,
+    symbols_by_assets: AHashMap<(AssetSymbol, AssetSymbol), InstrumentSymbol>,
+    /// Sorted symbols of instruments containing asset. Used to find cross routes
+    symbols_by_asset: AHashMap<AssetSymbol, Vec<InstrumentSymbol>>,
+}
+
+impl InstrumentsRegistry {
+    pub fn new(src: Vec<Instrument>) -> Self {
+        let mut registry = Self {
+            items: SortedVec::new_with_capacity(src.len()),
+            symbols_by_assets: AHashMap::with_capacity(src.len()),
+            symbols_by_asset: AHashMap::with_capacity(src.len()),
+        };
+
+        for item in src.into_iter() {
+            registry.add(item);
+        }
+
+        registry
+    }
+
+    pub fn add(&mut self, instrument: Instrument) {
+        self.remove(&instrument.symbol);
+
+        let key = (instrument.base_asset.clone(), instrument.quote_asset.clone());
+        self.symbols_by_assets.insert(key, instrument.symbol.clone());
+
+        for asset in [&instrument.base_asset, &instrument.quote_asset] {
+            let symbols = self.symbols_by_asset.entry(asset.clone()).or_default();
+
+            if let Err(index) = symbols.binary_search(&instrument.symbol) {
+                symbols.insert(index, instrument.symbol.clone());
+            }
+        }
+
+        self.items.insert_or_replace(instrument);
+    }
+
+    pub fn remove(&mut self, symbol: &InstrumentSymbol) -> Option<Instrument> {
+        let instrument = self.items.remove(symbol);
+
+        if let Some(instrument) = instrument.as_ref() {
+            let key = (instrument.base_asset.clone(), instrument.quote_asset.clone());
+            self.symbols_by_assets.remove(&key);
+
+            for asset in [&instrument.base_asset, &instrument.quote_asset] {
+                if let Some(symbols) = self.symbols_by_asset.get_mut(asset) {
+                    symbols.retain(|item| item != symbol);
+
+                    if symbols.is_empty() {
+                        self.symbols_by_asset.remove(asset);
+                    }
+                }
+            }
+        }
+
+        instrument
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn get(&self, symbol: &InstrumentSymbol) -> Option<&Instrument> {
+        self.items.get(symbol)
+    }
+
+    pub fn get_spec(&self, symbol: &InstrumentSymbol) -> Option<&InstrumentSpec> {
+        self.items.get(symbol)?.spec.as_ref()
+    }
+
+    /// Finds instrument symbol with exactly the same base and quote assets
+    pub fn find_symbol(&self, base_asset: &AssetSymbol, quote_asset: &AssetSymbol) -> Option<&InstrumentSymbol> {
+        self.symbols_by_assets.get(&(base_asset.clone(), quote_asset.clone()))
+    }
+
+    pub fn find(&self, base_asset: &AssetSymbol, quote_asset: &AssetSymbol) -> Option<&Instrument> {
+        let symbol = self.find_symbol(base_asset, quote_asset)?;
+
+        self.items.get(symbol)
+    }
+
+    /// Finds instrument by assets pair in any direction. Direct instrument is preferred over inverted
+    pub fn find_pair(&self, asset: &AssetSymbol, other_asset: &AssetSymbol) -> Option<&Instrument> {
+        self.find(asset, other_asset)
+            .or_else(|| self.find(other_asset, asset))
+    }
+
+    /// Finds instruments with asset as base or quote asset
+    pub fn find_by_asset(&self, asset: &AssetSymbol) -> impl Iterator<Item = &Instrument> {
+        self.symbols_by_asset
+            .get(asset)
+            .into_iter()
+            .flatten()
+            .filter_map(|symbol| self.items.get(symbol))
+    }
+
+    /// Finds instruments of all routes from asset to other asset through one intermediate asset
+    pub fn find_cross_pairs(&self, asset: &AssetSymbol, other_asset: &AssetSymbol) -> Vec<&Instrument> {
+        let mut cross_pairs: Vec<&Instrument> = Vec::new();
+
+        for instrument in self.find_by_asset(asset) {
+            let Some(intermediate_asset) = instrument.get_counter_asset(asset) else {
+                continue;
+            };
+
+            if intermediate_asset == other_asset {
+                continue;
+            }
+
+            let Some(second_instrument) = self.find_pair(intermediate_asset, other_asset) else {
+                continue;
+            };
+
+            for item in [instrument, second_instrument] {
+                if !cross_pairs.iter().any(|pair| pair.symbol == item.symbol) {
+                    cross_pairs.push(item);
+                }
+            }
+        }
+
+        cross_pairs
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Instrument> {
+        self.items.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Instrument, InstrumentSpec, InstrumentsRegistry};
+
+    #[test]
+    fn find_exotic_symbols() {
+        let registry = InstrumentsRegistry::new(vec![
+            Instrument::new("1000PEPEUSDT", "1000PEPE", "USDT"),
+            Instrument::new("BTC-PERP", "BTC", "USD"),
+            Instrument::new("ETHUSDT", "ETH", "USDT"),
+            Instrument::new("ETHWUSDT", "ETHW", "USDT"),
+        ]);
+
+        assert_eq!(
+            registry.find_symbol(&"1000PEPE".into(), &"USDT".into()),
+            Some(&"1000PEPEUSDT".into())
+        );
+        assert_eq!(registry.find_symbol(&"BTC".into(), &"USD".into()), Some(&"BTC-PERP".into()));
+        assert_eq!(registry.find_symbol(&"ETH".into(), &"USDT".into()), Some(&"ETHUSDT".into()));
+        assert_eq!(registry.find_symbol(&"BTC".into(), &"USDT".into()), None);
+    }
+
+    #[test]
+    fn find_inverted_pair() {
+        let registry = InstrumentsRegistry::new(vec![Instrument::new("USDTBTC", "USDT", "BTC")]);
+        let instrument = registry.find_pair(&"BTC".into(), &"USDT".into()).unwrap();
+
+        assert_eq!(instrument.symbol, "USDTBTC".into());
+        assert!(instrument.is_inverted_for(&"BTC".into()));
+    }
+
+    #[test]
+    fn replace_instrument_assets() {
+        let mut registry = InstrumentsRegistry::new(vec![Instrument::new("BTC-PERP", "BTC", "USD")]);
+        registry.add(Instrument::new("BTC-PERP", "BTC", "USDT"));
+
+        assert_eq!(registry.len(), 1);
+        assert_eq!(registry.find_symbol(&"BTC".into(), &"USD".into()), None);
+        assert_eq!(registry.find_symbol(&"BTC".into(), &"USDT".into()), Some(&"BTC-PERP".into()));
+        assert_eq!(registry.find_by_asset(&"USD".into()).count(), 0);
+        assert_eq!(registry.find_by_asset(&"USDT".into()).count(), 1);
+    }
+
+    #[test]
+    fn round_by_spec() {
+        let spec = InstrumentSpec {
+            tick_size: 0.05,
+            lot_step: 0.1,
+            min_volume: 1.0,
+            max_volume: Some(100.0),
+            max_leverage: Some(20.0),
+            pnl_accuracy: Some(2),
+        };
+
+        assert_eq!(spec.get_price_precision(), 2);
+        assert_eq!(spec.round_price(10.123), 10.1);
+        assert_eq!(spec.round_price(10.126), 10.15);
+        assert_eq!(spec.floor_volume(0.3), 0.3);
+        assert_eq!(spec.floor_volume(12.39), 12.3);
+        assert_eq!(spec.round_amount(1.239), 1.24);
+        assert!(!spec.is_volume_valid(0.5));
+        assert!(!spec.is_volume_valid(100.5));
+        assert!(spec.is_volume_valid(50.0));
+    }
+}
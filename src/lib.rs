@@ -6,22 +6,38 @@ pub mod monitoring;
 pub mod top_ups;
 pub mod wallets;
 pub mod instrument_symbol;
+pub mod instruments;
 pub mod position_id;
 pub mod asset_symbol;
 pub mod wallet_id;
 pub mod assets;
 pub mod sharding;
+pub mod errors;
+pub mod markups;
+pub mod quote_filters;
+pub mod order_books;
+pub mod valuations;
+pub mod candles;
+pub mod price_history;
+pub mod position_groups;
+pub mod increases;
+pub mod fundings;
+pub mod commissions;
+pub mod swaps;
+pub mod clock;
 
 pub use ahash::AHashMap;
 
 #[cfg(test)]
 mod tests {
+    use crate::instruments::{Instrument, InstrumentsRegistry};
     use crate::positions::BidAsk;
 
     #[test]
     fn test_get_instrument_symbol() {
-        let instrument_symbol = BidAsk::get_instrument_symbol(&"BTC".into(), &"USD".into());
+        let instruments = InstrumentsRegistry::new(vec![Instrument::new("BTCUSD", "BTC", "USD")]);
+        let instrument_symbol = BidAsk::get_instrument_symbol(&instruments, &"BTC".into(), &"USD".into());
 
-        assert_eq!(instrument_symbol, "BTCUSD".into());
+        assert_eq!(instrument_symbol, Some("BTCUSD".into()));
     }
 }
@@ -0,0 +1,179 @@
+use ahash::AHashMap;
+use rust_extensions::sorted_vec::{EntityWithKey, SortedVec};
+use crate::instrument_symbol::InstrumentSymbol;
+use crate::positions::BidAsk;
+
+/// Transforms raw liquidity provider quote into client quote
+#[derive(Clone, Debug)]
+pub struct MarkupProfile {
+    pub instrument: InstrumentSymbol,
+    /// Points subtracted from bid and added to ask
+    pub fixed_points: f64,
+    /// Price value of one point
+    pub point_size: f64,
+    /// Percent of mid price subtracted from bid and added to ask
+    pub percent: f64,
+    /// Min spread of client quote in price units
+    pub min_spread: f64,
+}
+
+impl MarkupProfile {
+    pub fn apply(&self, raw_bidask: &BidAsk) -> BidAsk {
+        let mid = (raw_bidask.bid + raw_bidask.ask) / 2.0;
+        let markup = self.fixed_points * self.point_size + mid * self.percent / 100.0;
+        let mut bid = raw_bidask.bid - markup;
+        let mut ask = raw_bidask.ask + markup;
+
+        if ask - bid < self.min_spread {
+            let mid = (bid + ask) / 2.0;
+            bid = mid - self.min_spread / 2.0;
+            ask = mid + self.min_spread / 2.0;
+        }
+
+        BidAsk {
+            instrument: raw_bidask.instrument.clone(),
+            datetime: raw_bidask.datetime,
+            bid,
+            ask,
+        }
+    }
+}
+
+impl EntityWithKey<InstrumentSymbol> for MarkupProfile {
+    fn get_key(&self) -> &InstrumentSymbol {
+        &self.instrument
+    }
+}
+
+/// Markup profiles by instruments with overrides by trader groups
+#[derive(Clone, Debug)]
+pub struct MarkupProfiles {
+    default_profiles: SortedVec<InstrumentSymbol, MarkupProfile>,
+    profiles_by_groups: AHashMap<String, SortedVec<InstrumentSymbol, MarkupProfile>>,
+}
+
+impl MarkupProfiles {
+    pub fn new() -> Self {
+        Self {
+            default_profiles: SortedVec::new(),
+            profiles_by_groups: AHashMap::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.default_profiles.is_empty() && self.profiles_by_groups.is_empty()
+    }
+
+    /// Adds profile for trader group or default profile if group is None
+    pub fn add(&mut self, trader_group: Option<&str>, profile: MarkupProfile) {
+        let Some(trader_group) = trader_group else {
+            self.default_profiles.insert_or_replace(profile);
+            return;
+        };
+
+        let profiles = self.profiles_by_groups.get_mut(trader_group);
+
+        if let Some(profiles) = profiles {
+            profiles.insert_or_replace(profile);
+        } else {
+            let mut profiles = SortedVec::new();
+            profiles.insert_or_replace(profile);
+            self.profiles_by_groups.insert(trader_group.to_string(), profiles);
+        }
+    }
+
+    pub fn remove(&mut self, trader_group: Option<&str>, instrument: &InstrumentSymbol) -> Option<MarkupProfile> {
+        let Some(trader_group) = trader_group else {
+            return self.default_profiles.remove(instrument);
+        };
+
+        let profiles = self.profiles_by_groups.get_mut(trader_group)?;
+        let profile = profiles.remove(instrument);
+
+        if profiles.is_empty() {
+            self.profiles_by_groups.remove(trader_group);
+        }
+
+        profile
+    }
+
+    /// Returns profile of trader group or default profile if group has no own profile for instrument
+    pub fn get(&self, trader_group: Option<&str>, instrument: &InstrumentSymbol) -> Option<&MarkupProfile> {
+        let group_profile = trader_group
+            .and_then(|group| self.profiles_by_groups.get(group))
+            .and_then(|profiles| profiles.get(instrument));
+
+        group_profile.or_else(|| self.default_profiles.get(instrument))
+    }
+
+    /// Returns trader groups with own profiles
+    pub fn get_trader_groups(&self) -> impl Iterator<Item = &str> {
+        self.profiles_by_groups.keys().map(|group| group.as_str())
+    }
+
+    /// Returns client quote or copy of raw quote if no profile found
+    pub fn apply(&self, trader_group: Option<&str>, raw_bidask: &BidAsk) -> BidAsk {
+        match self.get(trader_group, &raw_bidask.instrument) {
+            Some(profile) => profile.apply(raw_bidask),
+            None => raw_bidask.clone(),
+        }
+    }
+}
+
+impl Default for MarkupProfiles {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MarkupProfile, MarkupProfiles};
+    use crate::positions::BidAsk;
+
+    #[test]
+    fn apply_fixed_and_percent_markup() {
+        let profile = new_profile(2.0, 0.1, 0.0);
+        let raw_bidask = BidAsk::new_synthetic("BTCUSDT".into(), 999.0, 1001.0);
+
+        let bidask = profile.apply(&raw_bidask);
+
+        let markup = 2.0 * 0.01 + 1000.0 * 0.1 / 100.0;
+        assert_eq!(bidask.bid, 999.0 - markup);
+        assert_eq!(bidask.ask, 1001.0 + markup);
+    }
+
+    #[test]
+    fn apply_min_spread() {
+        let profile = new_profile(0.0, 0.0, 10.0);
+        let raw_bidask = BidAsk::new_synthetic("BTCUSDT".into(), 999.0, 1001.0);
+
+        let bidask = profile.apply(&raw_bidask);
+
+        assert_eq!(bidask.bid, 995.0);
+        assert_eq!(bidask.ask, 1005.0);
+    }
+
+    #[test]
+    fn get_group_profile_with_default_fallback() {
+        let mut profiles = MarkupProfiles::new();
+        profiles.add(None, new_profile(1.0, 0.0, 0.0));
+        profiles.add(Some("vip"), new_profile(0.0, 0.0, 0.0));
+
+        let vip_profile = profiles.get(Some("vip"), &"BTCUSDT".into()).unwrap();
+        let other_profile = profiles.get(Some("other"), &"BTCUSDT".into()).unwrap();
+
+        assert_eq!(vip_profile.fixed_points, 0.0);
+        assert_eq!(other_profile.fixed_points, 1.0);
+    }
+
+    fn new_profile(fixed_points: f64, percent: f64, min_spread: f64) -> MarkupProfile {
+        MarkupProfile {
+            instrument: "BTCUSDT".into(),
+            fixed_points,
+            point_size: 0.01,
+            percent,
+            min_spread,
+        }
+    }
+}
@@ -1,17 +1,31 @@
 use crate::asset_symbol::AssetSymbol;
 use crate::assets::AssetAmount;
+use crate::clock::{Clock, SystemClock};
+use crate::errors::TradingError;
+use crate::fundings::{FundingRate, FundingRates};
+use crate::swaps::{SwapRate, SwapRates};
+use crate::increases::PositionIncrease;
 use crate::instrument_symbol::InstrumentSymbol;
+use crate::instruments::{Instrument, InstrumentsRegistry};
+use crate::markups::MarkupProfiles;
+use crate::order_books::OrderBook;
+use crate::valuations::ValuationPrices;
+use crate::position_groups::{PositionGroup, PositionGroupType};
 use crate::position_id::PositionId;
 use crate::positions::PendingPosition;
 use crate::top_ups::{ActiveTopUp, CanceledTopUp};
 use crate::wallet_id::WalletId;
 use crate::wallets::{Wallet, WalletBalance};
+use crate::orders::{Order, OrderType};
 use crate::{
-    caches::PositionsCache,
-    positions::{ActivePosition, BidAsk, ClosedPosition, Position},
+    caches::{BidAsksCache, PositionsCache},
+    positions::{ActivePosition, BidAsk, ClosePositionReason, ClosedPosition, Position},
 };
 use ahash::{AHashMap, AHashSet};
+use rust_extensions::date_time::DateTimeAsMicroseconds;
 use rust_extensions::sorted_vec::{EntityWithKey, SortedVec};
+use std::collections::BTreeSet;
+use std::sync::Arc;
 use std::time::Duration;
 
 pub struct PositionIdsByInstrumentSymbol {
@@ -77,6 +91,7 @@ impl EntityWithKey<InstrumentSymbol> for WalletIdsByInstrumentSymbol {
 }
 
 pub struct PositionsMonitor {
+    instruments: InstrumentsRegistry,
     positions_cache: PositionsCache,
     ids_by_instruments: SortedVec<InstrumentSymbol, PositionIdsByInstrumentSymbol>,
     cancel_top_up_delay: Duration,
@@ -87,13 +102,34 @@ pub struct PositionsMonitor {
     wallet_ids_by_instruments: SortedVec<InstrumentSymbol, WalletIdsByInstrumentSymbol>,
     wallet_monitoring_enabled: bool,
     last_update_events_count: usize,
+    default_max_quote_age: Option<Duration>,
+    max_quote_ages: AHashMap<InstrumentSymbol, Duration>,
+    quote_dates: AHashMap<InstrumentSymbol, DateTimeAsMicroseconds>,
+    /// Monitored instruments without quotes by dates since they are monitored
+    unquoted_dates: AHashMap<InstrumentSymbol, DateTimeAsMicroseconds>,
+    /// Dates in microseconds after which quotes of instruments become stale
+    stale_deadlines: BTreeSet<(i64, InstrumentSymbol)>,
+    stale_deadlines_by_instruments: AHashMap<InstrumentSymbol, i64>,
+    /// Last raw quotes used to price assets by cross rates
+    bidasks: BidAsksCache,
+    stale_instruments: AHashSet<InstrumentSymbol>,
+    markups: MarkupProfiles,
+    order_books: AHashMap<InstrumentSymbol, OrderBook>,
+    valuation_prices: ValuationPrices,
+    funding_rates: FundingRates,
+    swap_rates: SwapRates,
+    clock: Arc<dyn Clock>,
+    groups: AHashMap<String, PositionGroup>,
+    group_ids_by_position_ids: AHashMap<PositionId, String>,
     // reused allocations
+    activated_group_ids: AHashSet<String>,
     top_up_pnls_by_wallet_ids: AHashMap<WalletId, f64>,
     top_up_reserved_by_wallet_ids: AHashMap<WalletId, SortedVec<AssetSymbol, AssetAmount>>,
 }
 
 impl PositionsMonitor {
     pub fn new(
+        instruments: InstrumentsRegistry,
         capacity: usize,
         cancel_top_up_delay: Duration,
         cancel_top_up_price_change_percent: f64,
@@ -104,6 +140,7 @@ impl PositionsMonitor {
         let wallet_ids_count = capacity / 20;
 
         Self {
+            instruments,
             wallets_by_ids: AHashMap::with_capacity(wallet_ids_count),
             positions_cache: PositionsCache::with_capacity(capacity),
             ids_by_instruments: SortedVec::new_with_capacity(instruments_count),
@@ -116,6 +153,23 @@ impl PositionsMonitor {
             top_up_reserved_by_wallet_ids: AHashMap::with_capacity(wallet_ids_count),
             wallet_monitoring_enabled,
             last_update_events_count: 0,
+            default_max_quote_age: None,
+            max_quote_ages: AHashMap::with_capacity(instruments_count),
+            quote_dates: AHashMap::with_capacity(instruments_count),
+            unquoted_dates: AHashMap::new(),
+            stale_deadlines: BTreeSet::new(),
+            stale_deadlines_by_instruments: AHashMap::with_capacity(instruments_count),
+            bidasks: BidAsksCache::new(Vec::new()),
+            stale_instruments: AHashSet::new(),
+            markups: MarkupProfiles::new(),
+            order_books: AHashMap::with_capacity(instruments_count),
+            valuation_prices: ValuationPrices::default(),
+            funding_rates: FundingRates::new(),
+            swap_rates: SwapRates::default(),
+            clock: Arc::new(SystemClock),
+            groups: AHashMap::new(),
+            group_ids_by_position_ids: AHashMap::new(),
+            activated_group_ids: AHashSet::new(),
         }
     }
 
@@ -123,6 +177,112 @@ impl PositionsMonitor {
         self.positions_cache.count()
     }
 
+    pub fn get_instruments(&self) -> &InstrumentsRegistry {
+        &self.instruments
+    }
+
+    /// Registers instrument. Positions added before must be re-added to be monitored by it
+    pub fn add_instrument(&mut self, instrument: Instrument) {
+        self.instruments.add(instrument);
+    }
+
+    /// Sets max quote age for instruments without own max quote age. None disables the check
+    pub fn set_default_max_quote_age(&mut self, max_age: Option<Duration>) {
+        self.default_max_quote_age = max_age;
+        self.reschedule_stale_checks();
+    }
+
+    pub fn set_max_quote_age(&mut self, instrument: InstrumentSymbol, max_age: Duration) {
+        self.max_quote_ages.insert(instrument, max_age);
+        self.reschedule_stale_checks();
+    }
+
+    pub fn remove_max_quote_age(&mut self, instrument: &InstrumentSymbol) -> Option<Duration> {
+        let max_age = self.max_quote_ages.remove(instrument);
+        self.reschedule_stale_checks();
+
+        max_age
+    }
+
+    pub fn get_max_quote_age(&self, instrument: &InstrumentSymbol) -> Option<Duration> {
+        self.max_quote_ages
+            .get(instrument)
+            .copied()
+            .or(self.default_max_quote_age)
+    }
+
+    /// Returns true if the last quote of instrument was older than max quote age during last update
+    pub fn is_quote_stale(&self, instrument: &InstrumentSymbol) -> bool {
+        self.stale_instruments.contains(instrument)
+    }
+
+    /// Sets markups applied to raw quotes passed to update before positions are updated
+    pub fn set_markups(&mut self, markups: MarkupProfiles) {
+        self.markups = markups;
+    }
+
+    pub fn get_markups(&self) -> &MarkupProfiles {
+        &self.markups
+    }
+
+    /// Sets order book used for activation and close prices of instrument positions
+    pub fn update_order_book(&mut self, order_book: OrderBook) {
+        self.order_books.insert(order_book.instrument.clone(), order_book);
+    }
+
+    pub fn remove_order_book(&mut self, instrument: &InstrumentSymbol) -> Option<OrderBook> {
+        self.order_books.remove(instrument)
+    }
+
+    /// Sets valuation price modes used for pnl, loss percent and stop-out of active positions
+    pub fn set_valuation_prices(&mut self, valuation_prices: ValuationPrices) {
+        self.valuation_prices = valuation_prices;
+    }
+
+    pub fn get_valuation_prices(&self) -> &ValuationPrices {
+        &self.valuation_prices
+    }
+
+    /// Sets mark price of instrument. Applied to positions on the next instrument quote
+    pub fn update_mark_price(&mut self, instrument: InstrumentSymbol, price: f64) {
+        self.valuation_prices.update_mark_price(instrument, price);
+    }
+
+    /// Sets funding rate charged from active positions of instrument during update
+    pub fn update_funding_rate(&mut self, rate: FundingRate) {
+        self.funding_rates.update(rate);
+    }
+
+    pub fn remove_funding_rate(&mut self, instrument: &InstrumentSymbol) -> Option<FundingRate> {
+        self.funding_rates.remove(instrument)
+    }
+
+    pub fn get_funding_rates(&self) -> &FundingRates {
+        &self.funding_rates
+    }
+
+    /// Sets swap rates and rollover time charged from active positions during update
+    pub fn set_swap_rates(&mut self, swap_rates: SwapRates) {
+        self.swap_rates = swap_rates;
+    }
+
+    pub fn get_swap_rates(&self) -> &SwapRates {
+        &self.swap_rates
+    }
+
+    /// Sets source of time for expiration, activation, close and top-up cancel dates
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    pub fn get_clock(&self) -> &Arc<dyn Clock> {
+        &self.clock
+    }
+
+    pub fn update_swap_rate(&mut self, rate: SwapRate) {
+        self.swap_rates.update(rate);
+    }
+
     pub fn get_wallet_mut(&mut self, wallet_id: &WalletId) -> Option<&mut Wallet> {
         let wallet = self.wallets_by_ids.get_mut(wallet_id);
 
@@ -145,6 +305,8 @@ impl PositionsMonitor {
         let position = self.positions_cache.remove(position_id);
 
         if let Some(position) = position.as_ref() {
+            self.remove_from_group(position_id);
+
             match position {
                 Position::Active(position) => {
                     if position.order.top_up_enabled
@@ -168,7 +330,7 @@ impl PositionsMonitor {
                 Position::Pending(_) => {}
             }
 
-            for instrument in position.get_instruments() {
+            for instrument in position.get_instruments(&self.instruments) {
                 if let Some(ids) = self.ids_by_instruments.get_mut(&instrument) {
                     ids.items.remove(position.get_id());
                 }
@@ -219,7 +381,7 @@ impl PositionsMonitor {
         &mut self,
         wallet_id: &WalletId,
         balance: WalletBalance,
-    ) -> Result<Option<Wallet>, String> {
+    ) -> Result<Option<Wallet>, TradingError> {
         let wallet = self.wallets_by_ids.get_mut(wallet_id);
 
         let Some(wallet) = wallet else {
@@ -233,9 +395,18 @@ impl PositionsMonitor {
 
     pub fn add(&mut self, position: Position) {
         let id = position.get_id().to_owned();
-        let instruments = position.get_instruments();
+        let instruments = position.get_instruments(&self.instruments);
+        let now = self.clock.now();
 
         for invest_instrument in instruments {
+            if !self.quote_dates.contains_key(&invest_instrument)
+                && !self.unquoted_dates.contains_key(&invest_instrument)
+            {
+                // instrument without quotes becomes stale after max quote age since it is monitored
+                self.unquoted_dates.insert(invest_instrument.clone(), now);
+                self.schedule_stale_check(&invest_instrument, now);
+            }
+
             if let Some(ids) = self.ids_by_instruments.get_mut(&invest_instrument) {
                 ids.items.insert(id.clone());
             } else {
@@ -260,20 +431,24 @@ impl PositionsMonitor {
         &mut self,
         position: &ActivePosition,
         top_up: ActiveTopUp,
-    ) -> Result<(), String> {
-        let position = self.positions_cache.get_mut(&position.id);
+    ) -> Result<(), TradingError> {
+        let cached_position = self.positions_cache.get_mut(&position.id);
 
-        let Some(position) = position else {
-            return Err("Position not found".to_string());
+        let Some(cached_position) = cached_position else {
+            return Err(TradingError::PositionNotFound(position.id.clone()));
         };
 
-        match position {
+        match cached_position {
             Position::Active(position) => {
                 position.add_top_up(top_up);
                 Ok(())
             }
-            Position::Closed(_) => Err("Can't add top-up to closed position ".to_string()),
-            Position::Pending(_) => Err("Can't add top-up to pending position".to_string()),
+            Position::Closed(_) => Err(TradingError::PositionStateMismatch(
+                "Can't add top-up to closed position".to_string(),
+            )),
+            Position::Pending(_) => Err(TradingError::PositionStateMismatch(
+                "Can't add top-up to pending position".to_string(),
+            )),
         }
     }
 
@@ -281,25 +456,610 @@ impl PositionsMonitor {
         self.positions_cache.get_mut(id)
     }
 
+    /// Closes fraction of active position. The rest stays monitored
+    pub fn close_part(
+        &mut self,
+        position_id: &PositionId,
+        fraction: f64,
+        reason: ClosePositionReason,
+    ) -> Result<Vec<PositionMonitoringEvent>, TradingError> {
+        let pnl_accuracy = self.pnl_accuracy;
+        let clock = self.clock.clone();
+        let part = self
+            .get_unlocked_active_mut(position_id)?
+            .close_part(fraction, reason, pnl_accuracy, clock.as_ref())?;
+        self.release_closed_part(&part);
+
+        Ok(vec![PositionMonitoringEvent::PositionClosed(part)])
+    }
+
+    /// Closes amount of invested asset of active position. The rest stays monitored
+    pub fn close_asset_amount(
+        &mut self,
+        position_id: &PositionId,
+        asset: &AssetSymbol,
+        amount: f64,
+        reason: ClosePositionReason,
+    ) -> Result<Vec<PositionMonitoringEvent>, TradingError> {
+        let pnl_accuracy = self.pnl_accuracy;
+        let clock = self.clock.clone();
+        let part = self
+            .get_unlocked_active_mut(position_id)?
+            .close_asset_amount(asset, amount, reason, pnl_accuracy, clock.as_ref())?;
+        self.release_closed_part(&part);
+
+        Ok(vec![PositionMonitoringEvent::PositionClosed(part)])
+    }
+
+    /// Deducts pnl and reserved balance of closed part from wallet of top-up position
+    fn release_closed_part(&mut self, part: &ClosedPosition) {
+        if !part.order.top_up_enabled {
+            return;
+        }
+
+        let Some(wallet) = self.wallets_by_ids.get_mut(&part.order.wallet_id) else {
+            return;
+        };
+
+        wallet.deduct_top_up_pnl(&part.order.instrument, part.pnl.unwrap_or_default());
+        wallet.deduct_top_up_reserved(&part.order.instrument, &part.total_invest_assets);
+    }
+
+    /// Adds invest assets to volume of active position at open price of quote
+    pub fn increase(
+        &mut self,
+        position_id: &PositionId,
+        bidask: &BidAsk,
+        invest_assets: &SortedVec<AssetSymbol, AssetAmount>,
+    ) -> Result<PositionIncrease, TradingError> {
+        let clock = self.clock.clone();
+
+        self.get_unlocked_active_mut(position_id)?
+            .increase(bidask, invest_assets, clock.as_ref())
+    }
+
+    /// Closes active position at client quote of raw quote and adds opposite position with the same invested assets
+    pub fn reverse(
+        &mut self,
+        position_id: &PositionId,
+        bidask: &BidAsk,
+    ) -> Result<(ClosedPosition, ActivePosition), TradingError> {
+        let clock = self.clock.clone();
+        self.get_unlocked_active_mut(position_id)?;
+        let client_bidask = self.update_active_by_quote(position_id, bidask);
+        let Some(Position::Active(position)) = self.positions_cache.get(position_id) else {
+            panic!("Checked");
+        };
+        let reversed = position.open_reversed(
+            client_bidask.as_ref().unwrap_or(bidask),
+            bidask,
+            &self.instruments,
+            clock.as_ref(),
+        )?;
+        let Some(Position::Active(position)) = self.remove(position_id) else {
+            panic!("Checked");
+        };
+        let closed = position.close(
+            ClosePositionReason::ClientCommand,
+            self.pnl_accuracy,
+            None,
+            clock.as_ref(),
+        );
+        self.add(Position::Active(reversed.clone()));
+
+        Ok((closed, reversed))
+    }
+
+    /// Reduces opposite active positions of reduce-only order wallet and instrument by order
+    /// invest assets at client quote of raw quote. The oldest positions are reduced first,
+    /// the rest of order amounts is ignored
+    pub fn reduce(
+        &mut self,
+        order: &Order,
+        bidask: &BidAsk,
+    ) -> Result<Vec<PositionMonitoringEvent>, TradingError> {
+        if !order.reduce_only {
+            return Err(TradingError::PositionStateMismatch(
+                "Order must be reduce-only to reduce positions".to_string(),
+            ));
+        }
+
+        if bidask.instrument != order.instrument {
+            return Err(TradingError::InvalidInstrument(format!(
+                "BidAsk instrument {} must be {}",
+                bidask.instrument, order.instrument
+            )));
+        }
+
+        let mut positions: Vec<(i64, PositionId)> = self
+            .positions_cache
+            .iter()
+            .filter_map(|position| match position {
+                Position::Active(position)
+                    if position.order.wallet_id == order.wallet_id
+                        && position.order.instrument == order.instrument
+                        && position.order.side != order.side
+                        && !self.locked_ids.contains(&position.id) =>
+                {
+                    Some((position.open_date.unix_microseconds, position.id.clone()))
+                }
+                _ => None,
+            })
+            .collect();
+
+        if positions.is_empty() {
+            return Err(TradingError::PositionStateMismatch(
+                "No opposite active position to reduce".to_string(),
+            ));
+        }
+
+        positions.sort_by_key(|(open_date, _)| *open_date);
+        let mut remaining_assets = order.invest_assets.clone();
+        let mut events = Vec::with_capacity(positions.len());
+
+        for (_, id) in positions.iter() {
+            if remaining_assets.iter().all(|item| item.amount <= 0.0) {
+                break;
+            }
+
+            self.update_active_by_quote(id, bidask);
+            let closed_position =
+                self.reduce_position(id, &mut remaining_assets, ClosePositionReason::ClientCommand);
+
+            if let Some(closed_position) = closed_position {
+                events.push(PositionMonitoringEvent::PositionClosed(closed_position));
+            }
+        }
+
+        Ok(events)
+    }
+
+    /// Updates active position by client quote of raw quote as monitor update does. Returns client quote if markup is applied
+    fn update_active_by_quote(&mut self, position_id: &PositionId, bidask: &BidAsk) -> Option<BidAsk> {
+        let Some(Position::Active(position)) = self.positions_cache.get_mut(position_id) else {
+            return None;
+        };
+
+        let client_bidask = get_client_bidask(&self.markups, &position.order, bidask);
+        position.update_with_valuation(
+            client_bidask.as_ref().unwrap_or(bidask),
+            &self.instruments,
+            &self.valuation_prices,
+        );
+        position.update_raw_price(bidask);
+
+        client_bidask
+    }
+
+    /// Closes active position by remaining amounts of invested assets. Closed amounts are deducted from remaining.
+    /// Returns None if position has no remaining assets to close
+    fn reduce_position(
+        &mut self,
+        id: &PositionId,
+        remaining_assets: &mut SortedVec<AssetSymbol, AssetAmount>,
+        reason: ClosePositionReason,
+    ) -> Option<ClosedPosition> {
+        let Some(Position::Active(position)) = self.positions_cache.get_mut(id) else {
+            return None;
+        };
+
+        let mut amounts = SortedVec::new_with_capacity(position.total_invest_assets.len());
+
+        for item in position.total_invest_assets.iter() {
+            let Some(remaining) = remaining_assets.get(&item.symbol) else {
+                continue;
+            };
+
+            if remaining.amount > 0.0 {
+                amounts.insert_or_replace(AssetAmount {
+                    amount: remaining.amount.min(item.amount),
+                    symbol: item.symbol.clone(),
+                });
+            }
+        }
+
+        if amounts.is_empty() {
+            return None;
+        }
+
+        let is_all_closed = position.total_invest_assets.iter().all(|item| {
+            amounts
+                .get(&item.symbol)
+                .map(|closed| closed.amount >= item.amount)
+                .unwrap_or(false)
+        });
+
+        let closed_position = if is_all_closed {
+            let Some(Position::Active(position)) = self.remove(id) else {
+                panic!("Checked");
+            };
+
+            position.close(reason, self.pnl_accuracy, None, self.clock.as_ref())
+        } else {
+            // amounts are limited by invested amounts and some amount stays in position
+            let part = position
+                .close_asset_amounts(&amounts, reason, self.pnl_accuracy, self.clock.as_ref())
+                .ok()?;
+            self.release_closed_part(&part);
+
+            part
+        };
+
+        for item in amounts.iter() {
+            if let Some(remaining) = remaining_assets.get_mut(&item.symbol) {
+                remaining.amount -= item.amount;
+            }
+        }
+
+        Some(closed_position)
+    }
+
+    fn get_unlocked_active_mut(
+        &mut self,
+        position_id: &PositionId,
+    ) -> Result<&mut ActivePosition, TradingError> {
+        if self.locked_ids.contains(position_id) {
+            return Err(TradingError::PositionStateMismatch(
+                "Position is locked".to_string(),
+            ));
+        }
+
+        match self.positions_cache.get_mut(position_id) {
+            Some(Position::Active(position)) => Ok(position),
+            Some(_) => Err(TradingError::PositionStateMismatch(
+                "Position is not active".to_string(),
+            )),
+            None => Err(TradingError::PositionNotFound(position_id.clone())),
+        }
+    }
+
+    /// Closes expired pending positions of all instruments including ones without recent quotes
+    pub fn close_expired(&mut self) -> Vec<PositionMonitoringEvent> {
+        let now = self.clock.now();
+        let expired_ids: Vec<PositionId> = self
+            .positions_cache
+            .iter()
+            .filter_map(|position| match position {
+                Position::Pending(position) if position.is_expired(now) => Some(position.id.clone()),
+                _ => None,
+            })
+            .filter(|id| !self.locked_ids.contains(id))
+            .collect();
+        let mut events = Vec::with_capacity(expired_ids.len());
+
+        for id in expired_ids.iter() {
+            let Some(Position::Pending(position)) = self.positions_cache.remove(id) else {
+                panic!("Checked");
+            };
+            let position = position.close(ClosePositionReason::Expired, self.clock.as_ref());
+            events.push(PositionMonitoringEvent::PositionExpired(position));
+        }
+
+        if !self.groups.is_empty() {
+            self.update_groups(None, &mut events, 0);
+        }
+
+        events
+    }
+
     fn clear_reused_allocations(&mut self) {
         self.top_up_pnls_by_wallet_ids.clear();
         self.top_up_reserved_by_wallet_ids.clear();
+        self.activated_group_ids.clear();
+    }
+
+    /// Links pending positions already added to monitor into group
+    pub fn add_group(&mut self, group: PositionGroup) -> Result<(), TradingError> {
+        for id in group.get_all_ids() {
+            let Some(position) = self.positions_cache.get_mut(id) else {
+                return Err(TradingError::PositionNotFound(id.clone()));
+            };
+
+            if !matches!(position, Position::Pending(_)) {
+                return Err(TradingError::PositionStateMismatch(format!(
+                    "Position {} in group must be pending",
+                    id
+                )));
+            }
+
+            if self.group_ids_by_position_ids.contains_key(id) {
+                return Err(TradingError::PositionStateMismatch(format!(
+                    "Position {} is already in group",
+                    id
+                )));
+            }
+        }
+
+        if let Some(entry_id) = group.entry_id.as_ref() {
+            let Some(Position::Pending(entry)) = self.positions_cache.get(entry_id) else {
+                panic!("Checked");
+            };
+
+            for id in group.ids.iter() {
+                let Some(Position::Pending(exit)) = self.positions_cache.get(id) else {
+                    panic!("Checked");
+                };
+
+                if exit.order.side == entry.order.side
+                    || exit.order.instrument != entry.order.instrument
+                    || exit.order.wallet_id != entry.order.wallet_id
+                {
+                    return Err(TradingError::PositionStateMismatch(format!(
+                        "Exit {} must be opposite to bracket entry {}",
+                        id, entry_id
+                    )));
+                }
+            }
+        }
+
+        for id in group.get_all_ids() {
+            self.group_ids_by_position_ids.insert(id.clone(), group.id.clone());
+        }
+
+        self.groups.insert(group.id.clone(), group);
+
+        Ok(())
+    }
+
+    /// Unlinks positions of group. Positions stay monitored
+    pub fn remove_group(&mut self, group_id: &str) -> Option<PositionGroup> {
+        let group = self.groups.remove(group_id)?;
+
+        for id in group.get_all_ids() {
+            self.group_ids_by_position_ids.remove(id);
+        }
+
+        Some(group)
+    }
+
+    pub fn get_group(&self, group_id: &str) -> Option<&PositionGroup> {
+        self.groups.get(group_id)
+    }
+
+    pub fn get_position_group(&self, position_id: &PositionId) -> Option<&PositionGroup> {
+        let group_id = self.group_ids_by_position_ids.get(position_id)?;
+
+        self.groups.get(group_id)
+    }
+
+    /// Applies group rules to positions activated and closed by events starting from index.
+    /// Reached bracket exits are filled by quote
+    fn update_groups(
+        &mut self,
+        bidask: Option<&BidAsk>,
+        events: &mut Vec<PositionMonitoringEvent>,
+        from_index: usize,
+    ) {
+        let mut index = from_index;
+
+        while index < events.len() {
+            let (position_id, is_activated) = match &events[index] {
+                PositionMonitoringEvent::PositionActivated(position) => (position.id.clone(), true),
+                PositionMonitoringEvent::PositionClosed(position)
+                | PositionMonitoringEvent::PositionExpired(position)
+                | PositionMonitoringEvent::PositionCanceled(position) => (position.id.clone(), false),
+                _ => {
+                    index += 1;
+                    continue;
+                }
+            };
+            index += 1;
+
+            let Some(group_id) = self.group_ids_by_position_ids.get(&position_id).cloned() else {
+                continue;
+            };
+
+            if is_activated {
+                self.on_group_position_activated(&group_id, &position_id, bidask, events);
+            } else {
+                self.on_group_position_closed(&group_id, &position_id, events);
+            }
+        }
+    }
+
+    fn on_group_position_activated(
+        &mut self,
+        group_id: &str,
+        position_id: &PositionId,
+        bidask: Option<&BidAsk>,
+        events: &mut Vec<PositionMonitoringEvent>,
+    ) {
+        let Some(group) = self.groups.get_mut(group_id) else {
+            return;
+        };
+
+        if group.group_type == PositionGroupType::Bracket && group.is_entry(position_id) {
+            group.is_entry_active = true;
+            let exit_id = group.ids.iter().find(|id| {
+                let Some(Position::Pending(exit)) = self.positions_cache.get(id) else {
+                    return false;
+                };
+
+                !self.locked_ids.contains(id)
+                    && !self.stale_instruments.contains(&exit.order.instrument)
+                    && exit.is_price_reached()
+            });
+
+            if let (Some(exit_id), Some(bidask)) = (exit_id.cloned(), bidask) {
+                self.fill_group_exit(group_id, &exit_id, bidask, events);
+            }
+
+            return;
+        }
+
+        let group = self.remove_group(group_id).expect("Checked");
+        self.cancel_group(group, Some(position_id), events);
+    }
+
+    fn on_group_position_closed(
+        &mut self,
+        group_id: &str,
+        position_id: &PositionId,
+        events: &mut Vec<PositionMonitoringEvent>,
+    ) {
+        let Some(group) = self.groups.get_mut(group_id) else {
+            return;
+        };
+
+        if group.is_entry(position_id) {
+            let group = self.remove_group(group_id).expect("Checked");
+            self.cancel_group(group, None, events);
+
+            return;
+        }
+
+        self.remove_from_group(position_id);
+    }
+
+    /// Removes position from group. Group is removed if it has no sense without position
+    fn remove_from_group(&mut self, position_id: &PositionId) {
+        let Some(group_id) = self.group_ids_by_position_ids.remove(position_id) else {
+            return;
+        };
+
+        let Some(group) = self.groups.get_mut(&group_id) else {
+            return;
+        };
+
+        group.ids.retain(|id| id != position_id);
+
+        let is_empty = match group.group_type {
+            _ if group.is_canceled => group.ids.is_empty(),
+            PositionGroupType::OneCancelsOther => group.ids.len() <= 1,
+            PositionGroupType::Bracket => group.is_entry(position_id) || group.ids.is_empty(),
+        };
+
+        if is_empty {
+            self.remove_group(&group_id);
+        }
+    }
+
+    /// Fills reached exit of bracket as reduce-only order: entry is closed by exit amounts and other exits are canceled
+    fn fill_group_exit(
+        &mut self,
+        group_id: &str,
+        exit_id: &PositionId,
+        bidask: &BidAsk,
+        events: &mut Vec<PositionMonitoringEvent>,
+    ) {
+        let Some(group) = self.groups.get(group_id).cloned() else {
+            return;
+        };
+        let Some(entry_id) = group.entry_id.clone() else {
+            return;
+        };
+
+        if self.locked_ids.contains(&entry_id) || self.locked_ids.contains(exit_id) {
+            return;
+        }
+
+        let Some(Position::Pending(exit)) = self.positions_cache.get(exit_id) else {
+            return;
+        };
+
+        if !matches!(self.positions_cache.get(&entry_id), Some(Position::Active(_))) {
+            return;
+        }
+
+        let reason = match exit.order.order_type {
+            OrderType::Limit => ClosePositionReason::TakeProfit,
+            _ => ClosePositionReason::StopLoss,
+        };
+        let mut remaining_assets = exit.total_invest_assets.clone();
+        self.update_active_by_quote(&entry_id, bidask);
+        let closed_entry = self.reduce_position(&entry_id, &mut remaining_assets, reason.clone());
+
+        let Some(closed_entry) = closed_entry else {
+            // exit without amounts of entry assets stays pending
+            return;
+        };
+
+        let Some(Position::Pending(exit)) = self.positions_cache.remove(exit_id) else {
+            panic!("Checked");
+        };
+        events.push(PositionMonitoringEvent::PositionClosed(
+            exit.close(reason, self.clock.as_ref()),
+        ));
+        events.push(PositionMonitoringEvent::PositionClosed(closed_entry));
+        // group is already removed if entry is closed
+        self.remove_group(group_id);
+        self.cancel_group(group, Some(exit_id), events);
+    }
+
+    /// Closes pending members of removed group except position. Locked members stay in
+    /// canceled group until they are unlocked
+    fn cancel_group(
+        &mut self,
+        mut group: PositionGroup,
+        except_id: Option<&PositionId>,
+        events: &mut Vec<PositionMonitoringEvent>,
+    ) {
+        let ids = std::mem::take(&mut group.ids);
+
+        for id in ids {
+            if Some(&id) == except_id {
+                continue;
+            }
+
+            if !self.cancel_group_position(&id, events) {
+                group.ids.push(id);
+            }
+        }
+
+        if !group.ids.is_empty() {
+            group.entry_id = None;
+            group.is_canceled = true;
+
+            for id in group.ids.iter() {
+                self.group_ids_by_position_ids.insert(id.clone(), group.id.clone());
+            }
+
+            self.groups.insert(group.id.clone(), group);
+        }
+    }
+
+    /// Closes pending position of group. Positions in other states are not affected.
+    /// Returns false if position is locked and isn't closed
+    fn cancel_group_position(&mut self, position_id: &PositionId, events: &mut Vec<PositionMonitoringEvent>) -> bool {
+        if !matches!(self.positions_cache.get(position_id), Some(Position::Pending(_))) {
+            return true;
+        }
+
+        if self.locked_ids.contains(position_id) {
+            return false;
+        }
+
+        let Some(Position::Pending(position)) = self.positions_cache.remove(position_id) else {
+            panic!("Checked");
+        };
+        let position = position.close(ClosePositionReason::GroupCanceled, self.clock.as_ref());
+        events.push(PositionMonitoringEvent::PositionCanceled(position));
+
+        true
     }
 
     pub fn update(&mut self, bidask: &BidAsk) -> Vec<PositionMonitoringEvent> {
+        let mut events = Vec::with_capacity(self.last_update_events_count / 4 + 10);
+        self.clock.on_quote(bidask);
+        let now = self.clock.now();
+        self.update_quote_date(bidask);
+        self.update_stale_quotes(&bidask.instrument, now, &mut events);
+        self.bidasks.update(bidask.clone());
+
         let position_ids = self.ids_by_instruments.get_mut(&bidask.instrument);
 
         let Some(position_ids) = position_ids else {
-            return Vec::with_capacity(0);
+            return events;
         };
 
-        let mut events = Vec::with_capacity(self.last_update_events_count / 4 + 10);
+        let events_start_index = events.len();
         let wallet_ids_to_remove_count = if self.wallet_monitoring_enabled { self.wallets_by_ids.len() / 1000 + 10 } else { 0 };
         let mut wallet_ids_to_remove = Vec::with_capacity(wallet_ids_to_remove_count);
+        let mut filled_group_exits = Vec::new();
 
         position_ids.items.retain(|position_id| {
             if self.locked_ids.contains(position_id) {
-                // skip update
+                // locked position is not updated, activated, expired or closed until it is unlocked
                 return true;
             }
 
@@ -309,6 +1069,20 @@ impl PositionsMonitor {
                 return false; // no position in cache so remove id from instruments map
             };
 
+            let is_price_stale =
+                has_stale_instruments(position, &self.instruments, &self.stale_instruments);
+            let group_id = self.group_ids_by_position_ids.get(position_id);
+            let group = group_id.and_then(|group_id| self.groups.get(group_id));
+            let is_group_canceled = group.map(|group| group.is_canceled).unwrap_or(false);
+            let is_group_blocked = group
+                .map(|group| {
+                    group.is_suspended(position_id) || self.activated_group_ids.contains(&group.id)
+                })
+                .unwrap_or(false);
+            let client_bidask = get_client_bidask(&self.markups, position.get_order(), bidask);
+            let client_bidask = client_bidask.as_ref().unwrap_or(bidask);
+            update_cross_asset_prices(position, &self.bidasks, &self.instruments);
+
             match position {
                 Position::Closed(_) => {
                     let position = match self.positions_cache.remove(position_id).expect("Checked")
@@ -320,42 +1094,96 @@ impl PositionsMonitor {
 
                     false // remove closed position
                 }
+                Position::Pending(_) if is_group_canceled => {
+                    let position =
+                        match self.positions_cache.remove(position_id).expect("Checked") {
+                            Position::Pending(position) => position,
+                            _ => panic!("Checked"),
+                        };
+                    let position =
+                        position.close(ClosePositionReason::GroupCanceled, self.clock.as_ref());
+                    events.push(PositionMonitoringEvent::PositionCanceled(position));
+
+                    false // remove unlocked position of canceled group
+                }
+                Position::Pending(position) if position.is_expired(now) => {
+                    let position =
+                        match self.positions_cache.remove(position_id).expect("Checked") {
+                            Position::Pending(position) => position,
+                            _ => panic!("Checked"),
+                        };
+                    let position =
+                        position.close(ClosePositionReason::Expired, self.clock.as_ref());
+                    events.push(PositionMonitoringEvent::PositionExpired(position));
+
+                    false // remove expired position
+                }
                 Position::Pending(position) => {
-                    position.update(bidask);
+                    position.update(client_bidask, &self.instruments);
+                    position.update_raw_price(bidask);
+
+                    if !is_price_stale && !is_group_blocked && position.is_price_reached() {
+                        let bracket_group = group.filter(|group| {
+                            group.group_type == PositionGroupType::Bracket && !group.is_entry(position_id)
+                        });
+
+                        if let Some(group) = bracket_group {
+                            // bracket exit reduces entry instead of opening position
+                            self.activated_group_ids.insert(group.id.clone());
+                            filled_group_exits.push((group.id.clone(), position_id.clone()));
+                        } else if position.can_activate(self.order_books.get(&position.order.instrument)) {
+                            if let Some(group) = group.filter(|group| !group.is_entry(position_id)) {
+                                self.activated_group_ids.insert(group.id.clone());
+                            }
 
-                    if position.is_price_reached() {
-                        if position.can_activate() {
                             let position =
                                 match self.positions_cache.remove(position_id).expect("Checked") {
                                     Position::Pending(position) => position,
                                     _ => panic!("Checked"),
                                 };
-                            let mut position =
-                                position.activate().expect("checked by can_activate");
-                            position.update(bidask);
+                            let order_book = self.order_books.get(&position.order.instrument);
+                            let mut position = position
+                                .activate(order_book, self.clock.as_ref())
+                                .expect("checked by can_activate");
+                            position.update_with_valuation(
+                                client_bidask,
+                                &self.instruments,
+                                &self.valuation_prices,
+                            );
+                            position.update_raw_price(bidask);
                             events
                                 .push(PositionMonitoringEvent::PositionActivated(position.clone()));
                             self.positions_cache.add(Position::Active(position));
-                        } else {
+                        } else if position.total_invest_assets.is_empty() {
                             self.locked_ids.insert_or_replace(position.id.clone());
                             let lock_reason =
                                 PositionLockReason::ActivationPending(position.clone());
                             events.push(PositionMonitoringEvent::PositionLocked(lock_reason));
                         }
+                        // otherwise activation waits for asset prices or order book depth at desire price
                     }
 
                     true // pending position must be monitored
                 }
                 Position::Active(position) => {
-                    position.update(bidask);
-
-                    if position.is_margin_call() {
+                    position.update_with_valuation(
+                        client_bidask,
+                        &self.instruments,
+                        &self.valuation_prices,
+                    );
+                    position.update_raw_price(bidask);
+                    position.accrue_funding(&self.funding_rates, now);
+                    position.accrue_swaps(&self.swap_rates, now);
+
+                    if !is_price_stale && position.is_margin_call() {
                         events.push(PositionMonitoringEvent::PositionMarginCall(
                             position.clone(),
                         ));
                     }
 
-                    if position.is_top_up() {
+                    if is_price_stale {
+                        // no decisions on stale prices
+                    } else if position.is_top_up() {
                         self.locked_ids.insert_or_replace(position.id.clone());
                         let event = PositionMonitoringEvent::PositionLocked(
                             PositionLockReason::TopUp(position.to_owned()),
@@ -365,6 +1193,7 @@ impl PositionsMonitor {
                         let canceled_top_ups = position.try_cancel_top_ups(
                             self.cancel_top_up_price_change_percent,
                             self.cancel_top_up_delay,
+                            self.clock.as_ref(),
                         );
 
                         if !canceled_top_ups.is_empty() {
@@ -378,7 +1207,13 @@ impl PositionsMonitor {
                         }
                     }
 
-                    if let Some(reason) = position.determine_close_reason() {
+                    let close_reason = if is_price_stale {
+                        None
+                    } else {
+                        position.determine_close_reason()
+                    };
+
+                    if let Some(reason) = close_reason {
                         let position = match self
                             .positions_cache
                             .remove(position_id)
@@ -387,7 +1222,13 @@ impl PositionsMonitor {
                             Position::Active(position) => position,
                             _ => panic!("Position is in Active case"),
                         };
-                        let position = position.close(reason, self.pnl_accuracy);
+                        let order_book = self.order_books.get(&position.order.instrument);
+                        let position = position.close(
+                            reason,
+                            self.pnl_accuracy,
+                            order_book,
+                            self.clock.as_ref(),
+                        );
 
                         if self.wallet_monitoring_enabled && self
                             .positions_cache
@@ -444,6 +1285,14 @@ impl PositionsMonitor {
             }
         });
 
+        for (group_id, exit_id) in filled_group_exits {
+            self.fill_group_exit(&group_id, &exit_id, bidask, &mut events);
+        }
+
+        if !self.groups.is_empty() {
+            self.update_groups(Some(bidask), &mut events, events_start_index);
+        }
+
         if self.wallet_monitoring_enabled {
             for wallet_id in wallet_ids_to_remove {
                 self.remove_wallet(&wallet_id);
@@ -462,6 +1311,95 @@ impl PositionsMonitor {
         events
     }
 
+    fn update_quote_date(&mut self, bidask: &BidAsk) {
+        let quote_date = self.quote_dates.get_mut(&bidask.instrument);
+
+        if let Some(quote_date) = quote_date {
+            if bidask.datetime.unix_microseconds <= quote_date.unix_microseconds {
+                return;
+            }
+
+            *quote_date = bidask.datetime;
+        } else {
+            self.quote_dates.insert(bidask.instrument.clone(), bidask.datetime);
+            self.unquoted_dates.remove(&bidask.instrument);
+        }
+
+        self.schedule_stale_check(&bidask.instrument, bidask.datetime);
+    }
+
+    /// Sets date after which quote of instrument received at date becomes stale
+    fn schedule_stale_check(&mut self, instrument: &InstrumentSymbol, date: DateTimeAsMicroseconds) {
+        if let Some(deadline) = self.stale_deadlines_by_instruments.remove(instrument) {
+            self.stale_deadlines.remove(&(deadline, instrument.clone()));
+        }
+
+        let Some(max_age) = self.get_max_quote_age(instrument) else {
+            self.stale_instruments.remove(instrument);
+            return;
+        };
+
+        let deadline = date.unix_microseconds + max_age.as_micros() as i64;
+        self.stale_deadlines.insert((deadline, instrument.clone()));
+        self.stale_deadlines_by_instruments.insert(instrument.clone(), deadline);
+    }
+
+    /// Recalculates stale deadlines of all instruments after max quote ages are changed
+    fn reschedule_stale_checks(&mut self) {
+        self.stale_deadlines.clear();
+        self.stale_deadlines_by_instruments.clear();
+        let dates: Vec<(InstrumentSymbol, DateTimeAsMicroseconds)> = self
+            .quote_dates
+            .iter()
+            .chain(self.unquoted_dates.iter())
+            .map(|(instrument, date)| (instrument.clone(), *date))
+            .collect();
+
+        for (instrument, date) in dates.iter() {
+            self.schedule_stale_check(instrument, *date);
+        }
+
+        // stale instruments are marked again by the next update
+        self.stale_instruments.clear();
+    }
+
+    /// Marks instruments with passed deadlines as stale and quoted instrument as fresh if its quote isn't stale
+    fn update_stale_quotes(
+        &mut self,
+        quoted_instrument: &InstrumentSymbol,
+        now: DateTimeAsMicroseconds,
+        events: &mut Vec<PositionMonitoringEvent>,
+    ) {
+        let is_quote_fresh = self
+            .stale_deadlines_by_instruments
+            .get(quoted_instrument)
+            .map(|deadline| *deadline >= now.unix_microseconds)
+            // deadline is removed when quote becomes stale
+            .unwrap_or_else(|| self.get_max_quote_age(quoted_instrument).is_none());
+
+        if is_quote_fresh {
+            self.stale_instruments.remove(quoted_instrument);
+        }
+
+        while let Some((deadline, _)) = self.stale_deadlines.first() {
+            if *deadline >= now.unix_microseconds {
+                break;
+            }
+
+            let (_, instrument) = self.stale_deadlines.pop_first().expect("Checked");
+            self.stale_deadlines_by_instruments.remove(&instrument);
+            let max_age = self.get_max_quote_age(&instrument).expect("Deadline is set by max age");
+
+            if self.stale_instruments.insert(instrument.clone()) {
+                events.push(PositionMonitoringEvent::QuoteStale(StaleQuoteInfo {
+                    quote_date: self.quote_dates.get(&instrument).copied(),
+                    instrument,
+                    max_age,
+                }));
+            }
+        }
+    }
+
     fn update_wallet_prices(&mut self, bidask: &BidAsk) {
         let wallet_ids = self.wallet_ids_by_instruments.get_mut(&bidask.instrument);
 
@@ -471,7 +1409,7 @@ impl PositionsMonitor {
                     .wallets_by_ids
                     .get_mut(wallet_id)
                     .expect("invalid wallet add");
-                wallet.update_price(bidask);
+                wallet.update_price(bidask, &self.instruments);
             }
         }
     }
@@ -529,6 +1467,13 @@ pub enum PositionMonitoringEvent {
     PositionLocked(PositionLockReason),
     /// Wallet has margin call
     WalletMarginCall(WalletMarginCallInfo),
+    /// Pending position reached expiration date of order time in force and was removed from cache
+    PositionExpired(ClosedPosition),
+    /// Pending position was canceled by another position of its group and removed from cache
+    PositionCanceled(ClosedPosition),
+    /// Instrument quote became older than max quote age. Positions depending on it
+    /// are not activated, topped-up or closed until a fresh quote is received
+    QuoteStale(StaleQuoteInfo),
 }
 
 pub enum PositionLockReason {
@@ -548,5 +1493,694 @@ pub struct WalletMarginCallInfo {
     pub trader_id: String,
 }
 
+#[derive(Debug)]
+pub struct StaleQuoteInfo {
+    pub instrument: InstrumentSymbol,
+    /// None if instrument has never been quoted since it is monitored
+    pub quote_date: Option<DateTimeAsMicroseconds>,
+    pub max_age: Duration,
+}
+
+/// Returns marked-up quote if bidask is a raw quote of order instrument with markup profile.
+/// Quotes of invest asset instruments are used as is
+fn get_client_bidask(markups: &MarkupProfiles, order: &Order, raw_bidask: &BidAsk) -> Option<BidAsk> {
+    if markups.is_empty() || order.instrument != raw_bidask.instrument {
+        return None;
+    }
+
+    markups
+        .get(order.trader_group.as_deref(), &raw_bidask.instrument)
+        .map(|profile| profile.apply(raw_bidask))
+}
+
+fn has_stale_instruments(
+    position: &Position,
+    instruments: &InstrumentsRegistry,
+    stale_instruments: &AHashSet<InstrumentSymbol>,
+) -> bool {
+    if stale_instruments.is_empty() {
+        return false;
+    }
+
+    position
+        .get_instruments(instruments)
+        .iter()
+        .any(|instrument| stale_instruments.contains(instrument))
+}
+
+/// Updates prices of invested assets without instrument to base asset by cross rates of last quotes.
+/// Prices which can't be resolved keep their last value
+fn update_cross_asset_prices(
+    position: &mut Position,
+    bidasks: &BidAsksCache,
+    instruments: &InstrumentsRegistry,
+) {
+    let (order, invest_assets, current_asset_prices) = match position {
+        Position::Active(position) => (
+            &position.order,
+            &position.total_invest_assets,
+            &mut position.current_asset_prices,
+        ),
+        Position::Pending(position) => (
+            &position.order,
+            &position.total_invest_assets,
+            &mut position.current_asset_prices,
+        ),
+        Position::Closed(_) => return,
+    };
+    let cross_assets: Vec<&AssetSymbol> = invest_assets
+        .iter()
+        .map(|item| &item.symbol)
+        .filter(|asset| {
+            **asset != order.base_asset && instruments.find_pair(asset, &order.base_asset).is_none()
+        })
+        .collect();
+
+    if cross_assets.is_empty() {
+        return;
+    }
+
+    let Ok(prices) = bidasks.try_find_prices(instruments, &order.base_asset, &cross_assets) else {
+        return;
+    };
+
+    for price in prices.iter() {
+        current_asset_prices.insert_or_replace(price.clone());
+    }
+}
+
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use super::{PositionMonitoringEvent, PositionsMonitor};
+    use crate::clock::{Clock, ManualClock, QuoteClock, SystemClock};
+    use crate::assets::{AssetAmount, AssetPrice};
+    use crate::commissions::CommissionSchedules;
+    use crate::instruments::{Instrument, InstrumentsRegistry};
+    use crate::errors::TradingError;
+    use crate::markups::{MarkupProfile, MarkupProfiles};
+    use crate::order_books::{OrderBook, OrderBookLevel};
+    use crate::orders::{OpenContext, Order, OrderSide, OrderType, TimeInForce};
+    use crate::position_groups::PositionGroup;
+    use crate::position_id::PositionId;
+    use crate::positions::{BidAsk, ClosePositionReason, Position};
+    use crate::valuations::{ValuationPriceMode, ValuationPrices};
+    use crate::wallet_id::WalletId;
+    use rust_extensions::date_time::DateTimeAsMicroseconds;
+    use rust_extensions::sorted_vec::SortedVec;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    #[test]
+    fn stale_quote_suppresses_stop_out() {
+        let mut monitor = new_monitor();
+        monitor.set_max_quote_age("ATOMUSDT".into(), Duration::from_secs(5));
+        monitor.add(new_order().open(&new_bidask(14.748, DateTimeAsMicroseconds::now()), &new_prices(), &OpenContext::new(&new_instruments(), &CommissionSchedules::new(), &SystemClock)));
+
+        let quote_date = DateTimeAsMicroseconds::now().sub(Duration::from_secs(60));
+        let events = monitor.update(&new_bidask(7.0, quote_date));
+
+        assert_eq!(monitor.count(), 1);
+        assert!(monitor.is_quote_stale(&"ATOMUSDT".into()));
+        assert!(events.iter().any(|e| matches!(e, PositionMonitoringEvent::QuoteStale(_))));
+        assert!(!events.iter().any(|e| matches!(e, PositionMonitoringEvent::PositionClosed(_))));
+
+        let events = monitor.update(&new_bidask(7.0, DateTimeAsMicroseconds::now()));
+
+        assert!(!monitor.is_quote_stale(&"ATOMUSDT".into()));
+        assert!(events.iter().any(|e| matches!(e, PositionMonitoringEvent::PositionClosed(_))));
+    }
+
+    #[test]
+    fn update_cross_asset_price_by_both_legs() {
+        let instruments = InstrumentsRegistry::new(vec![
+            Instrument::new("ATOMUSDT", "ATOM", "USDT"),
+            Instrument::new("EURBTC", "EUR", "BTC"),
+            Instrument::new("BTCUSDT", "BTC", "USDT"),
+        ]);
+        let mut monitor = PositionsMonitor::new(instruments, 100, Duration::from_secs(10), 1.0, None, false);
+        let mut order = new_order();
+        order.invest_assets = SortedVec::new();
+        order.invest_assets.insert_or_replace(AssetAmount {amount: 100.0, symbol: "EUR".into()});
+        let mut prices = new_prices();
+        prices.insert_or_replace(AssetPrice {price: 1.1, symbol: "EUR".into()});
+        let position = order.open(&new_bidask(14.748, DateTimeAsMicroseconds::now()), &prices, &OpenContext::new(&new_instruments(), &CommissionSchedules::new(), &SystemClock));
+        let id = position.get_id().clone();
+        monitor.add(position);
+
+        monitor.update(&BidAsk::new_synthetic("EURBTC".into(), 0.00002, 0.00002));
+        monitor.update(&BidAsk::new_synthetic("BTCUSDT".into(), 60000.0, 60000.0));
+
+        let Some(Position::Active(position)) = monitor.get_mut(&id) else {
+            panic!("Must be active position");
+        };
+        let eur_price = position.current_asset_prices.get(&"EUR".into()).unwrap().price;
+        assert!((eur_price - 1.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn never_quoted_invest_instrument_becomes_stale() {
+        let start = DateTimeAsMicroseconds::new(1_000_000);
+        let clock = Arc::new(ManualClock::new(start));
+        let instruments = InstrumentsRegistry::new(vec![
+            Instrument::new("ATOMUSDT", "ATOM", "USDT"),
+            Instrument::new("BTCUSDT", "BTC", "USDT"),
+        ]);
+        let mut monitor = PositionsMonitor::new(instruments, 100, Duration::from_secs(10), 1.0, None, false);
+        monitor.set_clock(clock.clone());
+        monitor.set_default_max_quote_age(Some(Duration::from_secs(5)));
+        let mut order = new_order();
+        order.invest_assets = SortedVec::new();
+        order.invest_assets.insert_or_replace(AssetAmount {amount: 0.01, symbol: "BTC".into()});
+        let mut prices = new_prices();
+        prices.insert_or_replace(AssetPrice {price: 20000.0, symbol: "BTC".into()});
+        monitor.add(order.open(&new_bidask(14.748, start), &prices, &OpenContext::new(&new_instruments(), &CommissionSchedules::new(), &SystemClock)));
+
+        clock.advance(Duration::from_secs(10));
+        let events = monitor.update(&new_bidask(7.0, clock.now()));
+
+        assert!(monitor.is_quote_stale(&"BTCUSDT".into()));
+        assert!(!monitor.is_quote_stale(&"ATOMUSDT".into()));
+        assert!(events.iter().any(|e| matches!(
+            e,
+            PositionMonitoringEvent::QuoteStale(info)
+                if info.instrument == "BTCUSDT".into() && info.quote_date.is_none()
+        )));
+        assert!(!events.iter().any(|e| matches!(
+            e,
+            PositionMonitoringEvent::PositionClosed(_) | PositionMonitoringEvent::PositionMarginCall(_)
+        )));
+        assert_eq!(monitor.count(), 1);
+    }
+
+    #[test]
+    fn mid_price_valuation_ignores_spread_widening() {
+        let mut monitor = new_monitor();
+        let mut valuation_prices = ValuationPrices::default();
+        valuation_prices.set_mode("ATOMUSDT".into(), ValuationPriceMode::MidPrice);
+        monitor.set_valuation_prices(valuation_prices);
+        monitor.add(new_order().open(&new_bidask(14.748, DateTimeAsMicroseconds::now()), &new_prices(), &OpenContext::new(&new_instruments(), &CommissionSchedules::new(), &SystemClock)));
+
+        let mut bidask = new_bidask(14.5, DateTimeAsMicroseconds::now());
+        bidask.bid = 12.0;
+        bidask.ask = 17.0;
+        let events = monitor.update(&bidask);
+
+        assert_eq!(monitor.count(), 1);
+        assert!(!events.iter().any(|e| matches!(e, PositionMonitoringEvent::PositionClosed(_))));
+    }
+
+    #[test]
+    fn expire_pending_position_by_quote_clock() {
+        let start = DateTimeAsMicroseconds::new(1_000_000);
+        let mut monitor = new_monitor();
+        monitor.set_clock(Arc::new(QuoteClock::new(start)));
+        let mut order = new_order();
+        order.order_type = OrderType::Limit;
+        order.desire_price = Some(10.0);
+        order.time_in_force = TimeInForce::GoodTillDate(start.add(Duration::from_secs(60)));
+        let position = order.open(&new_bidask(14.748, start), &new_prices(), &OpenContext::new(&new_instruments(), &CommissionSchedules::new(), monitor.get_clock().as_ref()));
+        assert_eq!(position.get_open_date().unix_microseconds, start.unix_microseconds);
+        monitor.add(position);
+
+        let events = monitor.update(&new_bidask(14.5, start.add(Duration::from_secs(30))));
+
+        assert_eq!(monitor.count(), 1);
+        assert!(events.is_empty());
+
+        let quote_date = start.add(Duration::from_secs(61));
+        let events = monitor.update(&new_bidask(14.5, quote_date));
+
+        assert_eq!(monitor.count(), 0);
+        assert!(matches!(
+            &events[..],
+            [PositionMonitoringEvent::PositionExpired(position)]
+                if position.close_date.unix_microseconds == quote_date.unix_microseconds
+        ));
+    }
+
+    #[test]
+    fn locked_pending_position_is_not_expired() {
+        let start = DateTimeAsMicroseconds::new(1_000_000);
+        let clock = Arc::new(ManualClock::new(start));
+        let mut monitor = new_monitor();
+        monitor.set_clock(clock.clone());
+        let mut order = new_order();
+        order.order_type = OrderType::Limit;
+        order.desire_price = Some(14.0);
+        order.time_in_force = TimeInForce::GoodTillDate(start.add(Duration::from_secs(60)));
+        let position = order.open(&new_bidask(14.748, start), &new_prices(), &OpenContext::new(&new_instruments(), &CommissionSchedules::new(), clock.as_ref()));
+        let id = position.get_id().clone();
+        monitor.add(position);
+
+        // reached pending position without reserved assets is locked
+        let events = monitor.update(&new_bidask(13.9, clock.now()));
+        assert!(events.iter().any(|e| matches!(e, PositionMonitoringEvent::PositionLocked(_))));
+
+        clock.advance(Duration::from_secs(120));
+        let events = monitor.update(&new_bidask(13.9, clock.now()));
+
+        assert!(events.is_empty());
+        assert!(monitor.close_expired().is_empty());
+        assert_eq!(monitor.count(), 1);
+
+        monitor.unlock(&id);
+        let events = monitor.update(&new_bidask(13.9, clock.now()));
+
+        assert!(matches!(&events[..], [PositionMonitoringEvent::PositionExpired(_)]));
+    }
+
+    #[test]
+    fn close_expired_pending_position() {
+        let mut monitor = new_monitor();
+        let mut order = new_order();
+        order.order_type = OrderType::Limit;
+        order.desire_price = Some(10.0);
+        order.time_in_force =
+            TimeInForce::GoodTillDate(DateTimeAsMicroseconds::now().sub(Duration::from_secs(1)));
+        monitor.add(order.open(&new_bidask(14.748, DateTimeAsMicroseconds::now()), &new_prices(), &OpenContext::new(&new_instruments(), &CommissionSchedules::new(), &SystemClock)));
+
+        let events = monitor.close_expired();
+
+        assert_eq!(monitor.count(), 0);
+        assert!(matches!(
+            &events[..],
+            [PositionMonitoringEvent::PositionExpired(position)]
+                if matches!(position.close_reason, ClosePositionReason::Expired)
+        ));
+    }
+
+    #[test]
+    fn close_expired_bracket_entry_cancels_exits() {
+        let mut monitor = new_monitor();
+        let wallet_id: WalletId = Uuid::new_v4().into();
+        let entry_id = add_pending(&mut monitor, &wallet_id, OrderType::Limit, OrderSide::Buy, 10.0);
+        let take_profit_id =
+            add_pending(&mut monitor, &wallet_id, OrderType::Limit, OrderSide::Sell, 12.0);
+        let stop_loss_id = add_pending(&mut monitor, &wallet_id, OrderType::Stop, OrderSide::Sell, 9.0);
+        let Some(Position::Pending(entry)) = monitor.get_mut(&entry_id) else {
+            panic!("Must be pending position");
+        };
+        entry.order.time_in_force =
+            TimeInForce::GoodTillDate(DateTimeAsMicroseconds::now().sub(Duration::from_secs(1)));
+        let group = PositionGroup::new_bracket(
+            "bracket",
+            entry_id.clone(),
+            vec![take_profit_id.clone(), stop_loss_id.clone()],
+        );
+        monitor.add_group(group).unwrap();
+
+        let events = monitor.close_expired();
+
+        assert!(matches!(&events[..], [
+            PositionMonitoringEvent::PositionExpired(entry),
+            PositionMonitoringEvent::PositionCanceled(take_profit),
+            PositionMonitoringEvent::PositionCanceled(stop_loss),
+        ] if entry.id == entry_id && take_profit.id == take_profit_id && stop_loss.id == stop_loss_id));
+        assert_eq!(monitor.count(), 0);
+        assert!(monitor.get_group("bracket").is_none());
+        assert!(monitor.get_position_group(&take_profit_id).is_none());
+    }
+
+    #[test]
+    fn limit_position_waits_for_depth_at_desire_price() {
+        let mut monitor = new_monitor();
+        let wallet_id: WalletId = Uuid::new_v4().into();
+        let id = add_pending(&mut monitor, &wallet_id, OrderType::Limit, OrderSide::Buy, 10.0);
+        monitor.update_order_book(OrderBook {
+            instrument: "ATOMUSDT".into(),
+            datetime: DateTimeAsMicroseconds::now(),
+            bids: vec![OrderBookLevel { price: 9.4, volume: 100.0 }],
+            asks: vec![
+                OrderBookLevel { price: 9.5, volume: 5.0 },
+                OrderBookLevel { price: 11.0, volume: 100.0 },
+            ],
+        });
+
+        let events = monitor.update(&new_bidask(9.5, DateTimeAsMicroseconds::now()));
+
+        assert!(events.is_empty());
+        assert!(!monitor.locked_ids.contains(&id));
+        assert!(matches!(monitor.get_mut(&id), Some(Position::Pending(_))));
+
+        monitor.update_order_book(OrderBook {
+            instrument: "ATOMUSDT".into(),
+            datetime: DateTimeAsMicroseconds::now(),
+            bids: vec![OrderBookLevel { price: 9.4, volume: 100.0 }],
+            asks: vec![OrderBookLevel { price: 9.5, volume: 100.0 }],
+        });
+        let events = monitor.update(&new_bidask(9.5, DateTimeAsMicroseconds::now()));
+
+        assert!(matches!(&events[..], [
+            PositionMonitoringEvent::PositionActivated(activated),
+        ] if activated.id == id && activated.activate_price == 9.5));
+    }
+
+    #[test]
+    fn one_cancels_other_group() {
+        let mut monitor = new_monitor();
+        let wallet_id: WalletId = Uuid::new_v4().into();
+        let buy_id = add_pending(&mut monitor, &wallet_id, OrderType::Limit, OrderSide::Buy, 10.0);
+        let sell_id = add_pending(&mut monitor, &wallet_id, OrderType::Limit, OrderSide::Sell, 20.0);
+        monitor
+            .add_group(PositionGroup::new_one_cancels_other("oco", vec![buy_id.clone(), sell_id.clone()]))
+            .unwrap();
+
+        let events = monitor.update(&new_bidask(9.5, DateTimeAsMicroseconds::now()));
+
+        assert_eq!(monitor.count(), 1);
+        assert!(matches!(&events[..], [
+            PositionMonitoringEvent::PositionActivated(activated),
+            PositionMonitoringEvent::PositionCanceled(canceled),
+        ] if activated.id == buy_id && canceled.id == sell_id));
+        assert!(monitor.get_group("oco").is_none());
+    }
+
+    #[test]
+    fn bracket_group_closes_entry_by_exit_after_entry() {
+        let mut monitor = new_monitor();
+        let wallet_id: WalletId = Uuid::new_v4().into();
+        let entry_id = add_pending(&mut monitor, &wallet_id, OrderType::Limit, OrderSide::Buy, 10.0);
+        let take_profit_id =
+            add_pending(&mut monitor, &wallet_id, OrderType::Limit, OrderSide::Sell, 12.0);
+        let stop_loss_id = add_pending(&mut monitor, &wallet_id, OrderType::Stop, OrderSide::Sell, 9.0);
+        let group = PositionGroup::new_bracket(
+            "bracket",
+            entry_id.clone(),
+            vec![take_profit_id.clone(), stop_loss_id.clone()],
+        );
+        monitor.add_group(group).unwrap();
+
+        let events = monitor.update(&new_bidask(13.0, DateTimeAsMicroseconds::now()));
+
+        assert!(events.is_empty());
+
+        let events = monitor.update(&new_bidask(8.5, DateTimeAsMicroseconds::now()));
+
+        assert!(matches!(&events[..], [
+            PositionMonitoringEvent::PositionActivated(entry),
+            PositionMonitoringEvent::PositionClosed(stop_loss),
+            PositionMonitoringEvent::PositionClosed(closed_entry),
+            PositionMonitoringEvent::PositionCanceled(take_profit),
+        ] if entry.id == entry_id
+            && stop_loss.id == stop_loss_id
+            && closed_entry.id == entry_id
+            && !closed_entry.is_partial
+            && matches!(closed_entry.close_reason, ClosePositionReason::StopLoss)
+            && take_profit.id == take_profit_id));
+        assert_eq!(monitor.count(), 0);
+        assert!(monitor.get_group("bracket").is_none());
+    }
+
+    #[test]
+    fn bracket_group_reduces_entry_by_exit_amount() {
+        let mut monitor = new_monitor();
+        let wallet_id: WalletId = Uuid::new_v4().into();
+        let entry_id = add_pending(&mut monitor, &wallet_id, OrderType::Limit, OrderSide::Buy, 10.0);
+        let take_profit_id =
+            add_pending(&mut monitor, &wallet_id, OrderType::Limit, OrderSide::Sell, 12.0);
+        let stop_loss_id = add_pending(&mut monitor, &wallet_id, OrderType::Stop, OrderSide::Sell, 9.0);
+        let Some(Position::Pending(take_profit)) = monitor.get_mut(&take_profit_id) else {
+            panic!("Must be pending position");
+        };
+        take_profit.total_invest_assets.insert_or_replace(AssetAmount {amount: 40.0, symbol: "USDT".into()});
+        let group = PositionGroup::new_bracket(
+            "bracket",
+            entry_id.clone(),
+            vec![take_profit_id.clone(), stop_loss_id.clone()],
+        );
+        monitor.add_group(group).unwrap();
+
+        monitor.update(&new_bidask(9.5, DateTimeAsMicroseconds::now()));
+        let events = monitor.update(&new_bidask(12.5, DateTimeAsMicroseconds::now()));
+
+        assert!(matches!(&events[..], [
+            PositionMonitoringEvent::PositionClosed(take_profit),
+            PositionMonitoringEvent::PositionClosed(closed_part),
+            PositionMonitoringEvent::PositionCanceled(stop_loss),
+        ] if take_profit.id == take_profit_id
+            && closed_part.is_partial
+            && closed_part.close_price == 12.5
+            && matches!(closed_part.close_reason, ClosePositionReason::TakeProfit)
+            && stop_loss.id == stop_loss_id));
+        let Some(Position::Active(entry)) = monitor.get_mut(&entry_id) else {
+            panic!("Must be active position");
+        };
+        assert_eq!(entry.total_invest_assets.get(&"USDT".into()).unwrap().amount, 60.0);
+        assert_eq!(monitor.count(), 1);
+    }
+
+    #[test]
+    fn bracket_group_keeps_exit_without_entry_amounts() {
+        let exit_assets = [None, Some(AssetAmount {amount: 40.0, symbol: "BTC".into()})];
+
+        for exit_asset in exit_assets {
+            let mut monitor = new_monitor();
+            let wallet_id: WalletId = Uuid::new_v4().into();
+            let entry_id = add_pending(&mut monitor, &wallet_id, OrderType::Limit, OrderSide::Buy, 10.0);
+            let take_profit_id =
+                add_pending(&mut monitor, &wallet_id, OrderType::Limit, OrderSide::Sell, 12.0);
+            let stop_loss_id = add_pending(&mut monitor, &wallet_id, OrderType::Stop, OrderSide::Sell, 9.0);
+            let Some(Position::Pending(take_profit)) = monitor.get_mut(&take_profit_id) else {
+                panic!("Must be pending position");
+            };
+            take_profit.total_invest_assets = SortedVec::new();
+
+            if let Some(exit_asset) = exit_asset {
+                take_profit.total_invest_assets.insert_or_replace(exit_asset);
+            }
+
+            let group = PositionGroup::new_bracket(
+                "bracket",
+                entry_id.clone(),
+                vec![take_profit_id.clone(), stop_loss_id.clone()],
+            );
+            monitor.add_group(group).unwrap();
+
+            monitor.update(&new_bidask(9.5, DateTimeAsMicroseconds::now()));
+            let events = monitor.update(&new_bidask(12.5, DateTimeAsMicroseconds::now()));
+
+            assert!(events.is_empty());
+            assert_eq!(monitor.count(), 3);
+            assert!(matches!(monitor.get_mut(&take_profit_id), Some(Position::Pending(_))));
+            assert_eq!(monitor.get_group("bracket").unwrap().ids, vec![take_profit_id, stop_loss_id]);
+            let Some(Position::Active(entry)) = monitor.get_mut(&entry_id) else {
+                panic!("Must be active position");
+            };
+            assert_eq!(entry.total_invest_assets.get(&"USDT".into()).unwrap().amount, 100.0);
+        }
+    }
+
+    #[test]
+    fn one_cancels_other_group_cancels_locked_position_after_unlock() {
+        let mut monitor = new_monitor();
+        let wallet_id: WalletId = Uuid::new_v4().into();
+        let buy_id = add_pending(&mut monitor, &wallet_id, OrderType::Limit, OrderSide::Buy, 10.0);
+        let sell_id = add_pending(&mut monitor, &wallet_id, OrderType::Limit, OrderSide::Sell, 20.0);
+        let Some(Position::Pending(sell)) = monitor.get_mut(&sell_id) else {
+            panic!("Must be pending position");
+        };
+        sell.total_invest_assets = SortedVec::new();
+        monitor
+            .add_group(PositionGroup::new_one_cancels_other("oco", vec![buy_id.clone(), sell_id.clone()]))
+            .unwrap();
+
+        // reached pending position without reserved assets is locked
+        let events = monitor.update(&new_bidask(20.5, DateTimeAsMicroseconds::now()));
+        assert!(matches!(&events[..], [PositionMonitoringEvent::PositionLocked(_)]));
+
+        let events = monitor.update(&new_bidask(9.5, DateTimeAsMicroseconds::now()));
+
+        assert!(matches!(&events[..], [
+            PositionMonitoringEvent::PositionActivated(activated),
+        ] if activated.id == buy_id));
+        assert_eq!(monitor.count(), 2);
+        let group = monitor.get_position_group(&sell_id).unwrap();
+        assert!(group.is_canceled);
+        assert_eq!(group.ids, vec![sell_id.clone()]);
+
+        monitor.unlock(&sell_id);
+        let events = monitor.update(&new_bidask(9.5, DateTimeAsMicroseconds::now()));
+
+        assert!(matches!(&events[..], [
+            PositionMonitoringEvent::PositionCanceled(canceled),
+        ] if canceled.id == sell_id));
+        assert_eq!(monitor.count(), 1);
+        assert!(monitor.get_group("oco").is_none());
+    }
+
+    #[test]
+    fn bracket_group_exit_must_be_opposite_to_entry() {
+        let mut monitor = new_monitor();
+        let wallet_id: WalletId = Uuid::new_v4().into();
+        let entry_id = add_pending(&mut monitor, &wallet_id, OrderType::Limit, OrderSide::Buy, 10.0);
+        let exit_id = add_pending(&mut monitor, &wallet_id, OrderType::Limit, OrderSide::Buy, 12.0);
+
+        assert!(monitor
+            .add_group(PositionGroup::new_bracket("bracket", entry_id, vec![exit_id]))
+            .is_err());
+    }
+
+    #[test]
+    fn close_part_emits_closed_part_event() {
+        let mut monitor = new_monitor();
+        let position = new_order().open(&new_bidask(14.748, DateTimeAsMicroseconds::now()), &new_prices(), &OpenContext::new(&new_instruments(), &CommissionSchedules::new(), &SystemClock));
+        let id = position.get_id().clone();
+        monitor.add(position);
+
+        let events = monitor.close_part(&id, 0.25, ClosePositionReason::ClientCommand).unwrap();
+
+        assert!(matches!(&events[..], [
+            PositionMonitoringEvent::PositionClosed(part),
+        ] if part.id != id && part.parent_id.as_ref() == Some(&id) && part.is_partial));
+        assert_eq!(monitor.count(), 1);
+    }
+
+    #[test]
+    fn reduce_only_order_reduces_opposite_position() {
+        let mut monitor = new_monitor();
+        let order = new_order();
+        let position = order.clone().open(&new_bidask(14.748, DateTimeAsMicroseconds::now()), &new_prices(), &OpenContext::new(&new_instruments(), &CommissionSchedules::new(), &SystemClock));
+        let id = position.get_id().clone();
+        monitor.add(position);
+
+        let mut reduce_order = order;
+        reduce_order.side = OrderSide::Sell;
+        reduce_order.reduce_only = true;
+        reduce_order.invest_assets.insert_or_replace(AssetAmount {amount: 40.0, symbol: "USDT".into()});
+        let bidask = new_bidask(15.0, DateTimeAsMicroseconds::now());
+        let events = monitor.reduce(&reduce_order, &bidask).unwrap();
+
+        assert!(matches!(&events[..], [
+            PositionMonitoringEvent::PositionClosed(closed),
+        ] if closed.is_partial
+            && closed.close_price == 15.0
+            && closed.total_invest_assets.get(&"USDT".into()).unwrap().amount == 40.0));
+        let Some(Position::Active(position)) = monitor.get_mut(&id) else {
+            panic!("Must be active position");
+        };
+        assert_eq!(position.total_invest_assets.get(&"USDT".into()).unwrap().amount, 60.0);
+
+        reduce_order.invest_assets.insert_or_replace(AssetAmount {amount: 100.0, symbol: "USDT".into()});
+        let events = monitor.reduce(&reduce_order, &bidask).unwrap();
+
+        assert!(matches!(&events[..], [
+            PositionMonitoringEvent::PositionClosed(closed),
+        ] if !closed.is_partial && closed.id == id));
+        assert_eq!(monitor.count(), 0);
+        assert!(matches!(
+            monitor.reduce(&reduce_order, &bidask),
+            Err(TradingError::PositionStateMismatch(_))
+        ));
+    }
+
+    #[test]
+    fn reverse_at_client_quote() {
+        let mut monitor = new_monitor();
+        let mut markups = MarkupProfiles::new();
+        markups.add(None, MarkupProfile {
+            instrument: "ATOMUSDT".into(),
+            fixed_points: 1.0,
+            point_size: 0.1,
+            percent: 0.0,
+            min_spread: 0.0,
+        });
+        monitor.set_markups(markups);
+        let position = new_order().open(&new_bidask(14.0, DateTimeAsMicroseconds::now()), &new_prices(), &OpenContext::new(&new_instruments(), &CommissionSchedules::new(), &SystemClock));
+        let id = position.get_id().clone();
+        monitor.add(position);
+
+        let (closed, reversed) = monitor
+            .reverse(&id, &new_bidask(15.0, DateTimeAsMicroseconds::now()))
+            .unwrap();
+
+        assert!((closed.close_price - 14.9).abs() < 1e-9);
+        assert_eq!(closed.close_raw_price, 15.0);
+        assert!((reversed.open_price - 14.9).abs() < 1e-9);
+        assert_eq!(reversed.open_raw_price, 15.0);
+    }
+
+    fn add_pending(
+        monitor: &mut PositionsMonitor,
+        wallet_id: &WalletId,
+        order_type: OrderType,
+        side: OrderSide,
+        price: f64,
+    ) -> PositionId {
+        let mut order = new_order();
+        let invest_assets = order.invest_assets.clone();
+        order.side = side;
+        order.wallet_id = wallet_id.clone();
+
+        if order_type == OrderType::Stop {
+            order.stop_price = Some(price);
+        } else {
+            order.desire_price = Some(price);
+        }
+
+        order.order_type = order_type;
+        let position = order.open(&new_bidask(14.748, DateTimeAsMicroseconds::now()), &new_prices(), &OpenContext::new(&new_instruments(), &CommissionSchedules::new(), &SystemClock));
+        let Position::Pending(mut position) = position else {
+            panic!("Must be pending position");
+        };
+        position.add_invest_assets(&invest_assets).unwrap();
+        let id = position.id.clone();
+        monitor.add(Position::Pending(position));
+
+        id
+    }
+
+    fn new_monitor() -> PositionsMonitor {
+        PositionsMonitor::new(new_instruments(), 100, Duration::from_secs(10), 1.0, None, false)
+    }
+
+    fn new_instruments() -> InstrumentsRegistry {
+        InstrumentsRegistry::new(vec![Instrument::new("ATOMUSDT", "ATOM", "USDT")])
+    }
+
+    fn new_bidask(price: f64, datetime: DateTimeAsMicroseconds) -> BidAsk {
+        BidAsk {
+            instrument: "ATOMUSDT".into(),
+            datetime,
+            bid: price,
+            ask: price,
+        }
+    }
+
+    fn new_prices() -> SortedVec<crate::asset_symbol::AssetSymbol, AssetPrice> {
+        let mut prices = SortedVec::new();
+        prices.insert_or_replace(AssetPrice {price: 1.0, symbol: "USDT".into()});
+
+        prices
+    }
+
+    fn new_order() -> Order {
+        let mut invest_assets = SortedVec::new();
+        invest_assets.insert_or_replace(AssetAmount {amount: 100.0, symbol: "USDT".into()});
+
+        Order {
+            base_asset: "USDT".into(),
+            id: "test".to_string(),
+            instrument: "ATOMUSDT".into(),
+            trader_id: "test".to_string(),
+            wallet_id: Uuid::new_v4().into(),
+            created_date: DateTimeAsMicroseconds::now(),
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::GoodTillCanceled,
+            desire_price: None,
+            stop_price: None,
+            funding_fee_period: None,
+            invest_assets,
+            leverage: 1.0,
+            side: OrderSide::Buy,
+            take_profit: None,
+            stop_loss: None,
+            stop_out_percent: 10.0,
+            margin_call_percent: 5.0,
+            top_up_enabled: false,
+            top_up_percent: 10.0,
+            trader_group: None,
+            reduce_only: false,
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use rust_extensions::date_time::DateTimeAsMicroseconds;
+use crate::instrument_symbol::InstrumentSymbol;
+use crate::orders::OrderSide;
+
+#[derive(Clone, Debug)]
+pub struct OrderBookLevel {
+    pub price: f64,
+    /// Volume in instrument base asset
+    pub volume: f64,
+}
+
+/// Order book depth. Levels are sorted from the best price
+#[derive(Clone, Debug)]
+pub struct OrderBook {
+    pub instrument: InstrumentSymbol,
+    pub datetime: DateTimeAsMicroseconds,
+    pub bids: Vec<OrderBookLevel>,
+    pub asks: Vec<OrderBookLevel>,
+}
+
+impl OrderBook {
+    /// Calculates VWAP open price for volume in instrument base asset
+    pub fn get_open_price(&self, side: &OrderSide, volume: f64) -> Option<f64> {
+        match side {
+            OrderSide::Buy => calculate_vwap(&self.asks, volume),
+            OrderSide::Sell => calculate_vwap(&self.bids, volume),
+        }
+    }
+
+    /// Calculates VWAP close price for volume in instrument base asset
+    pub fn get_close_price(&self, side: &OrderSide, volume: f64) -> Option<f64> {
+        match side {
+            OrderSide::Buy => calculate_vwap(&self.bids, volume),
+            OrderSide::Sell => calculate_vwap(&self.asks, volume),
+        }
+    }
+
+    /// Calculates VWAP open price for volume with markup of client price over raw price.
+    /// Returns client and raw prices
+    pub fn get_client_open_price(
+        &self,
+        side: &OrderSide,
+        volume: f64,
+        client_price: f64,
+        raw_price: f64,
+    ) -> Option<(f64, f64)> {
+        let depth_raw_price = self.get_open_price(side, volume)?;
+
+        Some((add_markup(depth_raw_price, client_price, raw_price), depth_raw_price))
+    }
+
+    /// Calculates VWAP close price for volume with markup of client price over raw price.
+    /// Returns client and raw prices
+    pub fn get_client_close_price(
+        &self,
+        side: &OrderSide,
+        volume: f64,
+        client_price: f64,
+        raw_price: f64,
+    ) -> Option<(f64, f64)> {
+        let depth_raw_price = self.get_close_price(side, volume)?;
+
+        Some((add_markup(depth_raw_price, client_price, raw_price), depth_raw_price))
+    }
+}
+
+/// Adds markup of client price over raw price to raw VWAP price
+fn add_markup(depth_raw_price: f64, client_price: f64, raw_price: f64) -> f64 {
+    depth_raw_price + client_price - raw_price
+}
+
+/// Fills volume level by level. Volume exceeding the depth is filled at the worst level price
+fn calculate_vwap(levels: &[OrderBookLevel], volume: f64) -> Option<f64> {
+    let last_level = levels.last()?;
+
+    if volume <= 0.0 {
+        return levels.first().map(|level| level.price);
+    }
+
+    let mut remaining_volume = volume;
+    let mut filled_quote_volume = 0.0;
+
+    for level in levels.iter() {
+        let filled_volume = remaining_volume.min(level.volume);
+        filled_quote_volume += filled_volume * level.price;
+        remaining_volume -= filled_volume;
+
+        if remaining_volume <= 0.0 {
+            break;
+        }
+    }
+
+    if remaining_volume > 0.0 {
+        filled_quote_volume += remaining_volume * last_level.price;
+    }
+
+    Some(filled_quote_volume / volume)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{OrderBook, OrderBookLevel};
+    use crate::orders::OrderSide;
+    use rust_extensions::date_time::DateTimeAsMicroseconds;
+
+    #[test]
+    fn small_volume_filled_at_best_price() {
+        let book = new_book();
+
+        assert_eq!(book.get_open_price(&OrderSide::Buy, 0.1), Some(100.0));
+        assert_eq!(book.get_close_price(&OrderSide::Buy, 0.1), Some(99.0));
+    }
+
+    #[test]
+    fn large_volume_filled_by_levels() {
+        let book = new_book();
+
+        // 1 unit at 100.0 and 1 unit at 200.0
+        let price = book.get_open_price(&OrderSide::Buy, 2.0).unwrap();
+
+        assert_eq!(price, 150.0);
+    }
+
+    #[test]
+    fn volume_over_depth_filled_at_worst_price() {
+        let book = new_book();
+
+        // 1 unit at 100.0 and 3 units at 200.0
+        let price = book.get_open_price(&OrderSide::Buy, 4.0).unwrap();
+
+        assert_eq!(price, 175.0);
+    }
+
+    #[test]
+    fn client_price_keeps_markup_over_raw_price() {
+        let book = new_book();
+
+        // client ask 101.0 is marked-up by 1.0 over raw ask 100.0
+        let prices = book.get_client_open_price(&OrderSide::Buy, 2.0, 101.0, 100.0);
+
+        assert_eq!(prices, Some((151.0, 150.0)));
+    }
+
+    fn new_book() -> OrderBook {
+        OrderBook {
+            instrument: "BTCUSDT".into(),
+            datetime: DateTimeAsMicroseconds::now(),
+            bids: vec![
+                OrderBookLevel { price: 99.0, volume: 1.0 },
+                OrderBookLevel { price: 98.0, volume: 1.0 },
+            ],
+            asks: vec![
+                OrderBookLevel { price: 100.0, volume: 1.0 },
+                OrderBookLevel { price: 200.0, volume: 1.0 },
+            ],
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use crate::{
-    calculations::calculate_total_amount,
+    calculations::{calculate_total_amount, try_calculate_total_amount},
     positions::{ActivePosition, BidAsk, PendingPosition, Position},
 };
 use num_enum::{IntoPrimitive, TryFromPrimitive};
@@ -8,8 +8,14 @@ use std::{time::Duration};
 use rust_extensions::sorted_vec::SortedVec;
 use uuid::Uuid;
 use crate::assets::{AssetAmount, AssetPrice};
+use crate::clock::{Clock, QuoteClock};
+use crate::commissions::{CommissionSchedule, CommissionSchedules};
+use crate::errors::TradingError;
 use crate::asset_symbol::AssetSymbol;
 use crate::instrument_symbol::InstrumentSymbol;
+use crate::instruments::{InstrumentSpec, InstrumentsRegistry};
+use crate::markups::MarkupProfiles;
+use crate::order_books::OrderBook;
 use crate::position_id::PositionId;
 use crate::wallet_id::WalletId;
 
@@ -30,15 +36,92 @@ pub struct Order {
     pub margin_call_percent: f64,
     pub top_up_enabled: bool,
     pub top_up_percent: f64,
+    /// Funding is charged at UTC timestamps which are multiples of the period
     pub funding_fee_period: Option<Duration>,
+    pub order_type: OrderType,
+    /// Lifetime of pending position opened by order
+    pub time_in_force: TimeInForce,
+    /// Execution price of limit and stop-limit orders
     pub desire_price: Option<f64>,
+    /// Trigger price of stop and stop-limit orders
+    pub stop_price: Option<f64>,
+    /// Selects markup profiles applied to raw quotes for the order
+    pub trader_group: Option<String>,
+    /// Order can only reduce opposite active positions of the same wallet and instrument
+    pub reduce_only: bool,
 }
 
-#[derive(Clone, IntoPrimitive, TryFromPrimitive)]
+#[derive(Debug, PartialEq, Clone, IntoPrimitive, TryFromPrimitive)]
 #[repr(i32)]
 pub enum OrderType {
     Market = 0,
     Limit = 1,
+    /// Opens at market when stop price is reached
+    Stop = 2,
+    /// Becomes limit order when stop price is reached
+    StopLimit = 3,
+}
+
+#[derive(Debug, Clone)]
+pub enum TimeInForce {
+    /// Pending position lives until it is activated or canceled
+    GoodTillCanceled,
+    /// Pending position expires at date
+    GoodTillDate(DateTimeAsMicroseconds),
+    /// Pending position expires at the end of UTC day of order creation
+    Day,
+}
+
+impl TimeInForce {
+    pub fn get_expiration_date(&self, created_date: DateTimeAsMicroseconds) -> Option<DateTimeAsMicroseconds> {
+        match self {
+            TimeInForce::GoodTillCanceled => None,
+            TimeInForce::GoodTillDate(date) => Some(*date),
+            TimeInForce::Day => {
+                let day = Duration::from_secs(60 * 60 * 24).as_micros() as i64;
+                let day_start = created_date.unix_microseconds
+                    - created_date.unix_microseconds.rem_euclid(day);
+
+                Some(DateTimeAsMicroseconds::new(day_start + day))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OrderViolation {
+    /// Leverage is not a positive number or is greater than instrument max leverage
+    InvalidLeverage { leverage: f64, max_leverage: Option<f64> },
+    StopOutNotAboveMarginCall { stop_out_percent: f64, margin_call_percent: f64 },
+    TopUpNotBelowStopOut { top_up_percent: f64, stop_out_percent: f64 },
+    /// Take profit price is on the loss side of current close price
+    InvalidTakeProfit { value: f64, close_price: f64 },
+    /// Stop loss price is on the profit side of current close price
+    InvalidStopLoss { value: f64, close_price: f64 },
+    /// Trailing units can be used by stop loss only
+    UnsupportedTakeProfitUnit(AutoClosePositionUnit),
+    /// Trailing stop loss distance is not a positive number
+    InvalidTrailingDistance(f64),
+    /// Break-even profit of stop loss is not a positive number
+    InvalidBreakEvenProfit(f64),
+    EmptyInvestAssets,
+    InvalidInvestAmount { symbol: AssetSymbol, amount: f64 },
+    MissingPrice(AssetSymbol),
+    InvalidInstrument(InstrumentSymbol),
+    MissingDesirePrice,
+    InvalidDesirePrice(f64),
+    MissingStopPrice,
+    InvalidStopPrice(f64),
+    /// Desire price of limit buy is above ask or desire price of limit sell is below bid
+    InvalidDesirePriceSide { desire_price: f64, open_price: f64 },
+    /// Stop price of buy is not above ask or stop price of sell is not below bid
+    InvalidStopPriceSide { stop_price: f64, open_price: f64 },
+    /// Desire price of stop-limit buy is above stop price or desire price of stop-limit sell is below stop price
+    InvalidStopLimitPrice { stop_price: f64, desire_price: f64 },
+    /// Volume in base asset is out of instrument volume limits
+    InvalidVolume { volume: f64, min_volume: f64, max_volume: Option<f64> },
+    /// Reduce-only order can't open position
+    ReduceOnly,
 }
 
 #[derive(Debug, PartialEq, Clone, IntoPrimitive, TryFromPrimitive)]
@@ -62,6 +145,8 @@ impl TakeProfitConfig {
                 OrderSide::Buy => self.value <= close_price,
                 OrderSide::Sell => self.value >= close_price,
             },
+            AutoClosePositionUnit::TrailingPriceUnit
+            | AutoClosePositionUnit::TrailingPercentUnit => false,
         }
     }
 }
@@ -70,9 +155,12 @@ impl TakeProfitConfig {
 pub struct StopLossConfig {
     pub value: f64,
     pub unit: AutoClosePositionUnit,
+    /// Moves stop loss to break-even price once pnl in base asset reaches the profit
+    pub break_even_profit: Option<f64>,
 }
 
 impl StopLossConfig {
+    /// Trailing stop loss is triggered by position stop loss price instead
     pub fn is_triggered(&self, pnl: f64, close_price: f64, side: &OrderSide) -> bool {
         match self.unit {
             AutoClosePositionUnit::AssetAmountUnit => pnl < 0.0 && pnl.abs() >= self.value,
@@ -80,48 +168,335 @@ impl StopLossConfig {
                 OrderSide::Buy => self.value >= close_price,
                 OrderSide::Sell => self.value <= close_price,
             },
+            AutoClosePositionUnit::TrailingPriceUnit
+            | AutoClosePositionUnit::TrailingPercentUnit => false,
+        }
+    }
+
+    pub fn is_trailing(&self) -> bool {
+        matches!(
+            self.unit,
+            AutoClosePositionUnit::TrailingPriceUnit | AutoClosePositionUnit::TrailingPercentUnit
+        )
+    }
+
+    /// Calculates trailing stop loss price at distance from close price
+    pub fn get_trailing_price(&self, close_price: f64, side: &OrderSide) -> Option<f64> {
+        let distance = match self.unit {
+            AutoClosePositionUnit::TrailingPriceUnit => self.value,
+            AutoClosePositionUnit::TrailingPercentUnit => close_price * self.value / 100.0,
+            _ => return None,
+        };
+
+        match side {
+            OrderSide::Buy => Some(close_price - distance),
+            OrderSide::Sell => Some(close_price + distance),
         }
     }
 }
 
-#[derive(Debug, Clone, IntoPrimitive, TryFromPrimitive)]
+#[derive(Debug, PartialEq, Clone, IntoPrimitive, TryFromPrimitive)]
 #[repr(i32)]
 pub enum AutoClosePositionUnit {
     AssetAmountUnit = 0,
     PriceRateUnit = 1,
+    /// Stop loss price follows close price at fixed price distance
+    TrailingPriceUnit = 2,
+    /// Stop loss price follows close price at distance in percent of close price
+    TrailingPercentUnit = 3,
+}
+
+/// Trading parameters and price sources used to open orders
+#[derive(Clone, Copy)]
+pub struct OpenContext<'a> {
+    pub instruments: &'a InstrumentsRegistry,
+    pub commissions: &'a CommissionSchedules,
+    pub clock: &'a dyn Clock,
+    /// Markup profiles applied to quote to get client quote. Quote is client quote if not set
+    pub markups: Option<&'a MarkupProfiles>,
+    /// Raw quote kept for hedging and reporting. Quote is raw quote if not set
+    pub raw_bidask: Option<&'a BidAsk>,
+    /// Raw order book for VWAP open and activate prices
+    pub order_book: Option<&'a OrderBook>,
+}
+
+impl<'a> OpenContext<'a> {
+    pub fn new(
+        instruments: &'a InstrumentsRegistry,
+        commissions: &'a CommissionSchedules,
+        clock: &'a dyn Clock,
+    ) -> Self {
+        Self {
+            instruments,
+            commissions,
+            clock,
+            markups: None,
+            raw_bidask: None,
+            order_book: None,
+        }
+    }
+
+    pub fn with_markups(mut self, markups: &'a MarkupProfiles) -> Self {
+        self.markups = Some(markups);
+
+        self
+    }
+
+    pub fn with_raw_bidask(mut self, raw_bidask: &'a BidAsk) -> Self {
+        self.raw_bidask = Some(raw_bidask);
+
+        self
+    }
+
+    pub fn with_order_book(mut self, order_book: &'a OrderBook) -> Self {
+        self.order_book = Some(order_book);
+
+        self
+    }
 }
 
 impl Order {
-    /// returns vec of instruments invested by order
-    pub fn get_invest_instruments(&self) -> Vec<InstrumentSymbol> {
-        let mut instruments = Vec::with_capacity(self.invest_assets.len());
+    /// returns vec of registered direct or inverted instruments invested by order
+    pub fn get_invest_instruments(&self, instruments: &InstrumentsRegistry) -> Vec<InstrumentSymbol> {
+        let mut invest_instruments = Vec::with_capacity(self.invest_assets.len());
 
         for asset in self.invest_assets.iter() {
-            let instrument = BidAsk::get_instrument_symbol(&asset.symbol, &self.base_asset);
-            instruments.push(instrument);
+            if let Some(instrument) = instruments.find_pair(&asset.symbol, &self.base_asset) {
+                invest_instruments.push(instrument.symbol.clone());
+            }
         }
 
-        instruments
+        invest_instruments
     }
 
-    /// returns vec of all possible instruments
-    pub fn get_instruments(&self) -> Vec<InstrumentSymbol> {
-        let mut instruments = Vec::with_capacity(self.invest_assets.len() + 1);
-        instruments.push(self.instrument.clone());
+    /// returns vec of all possible registered direct or inverted instruments
+    pub fn get_instruments(&self, instruments: &InstrumentsRegistry) -> Vec<InstrumentSymbol> {
+        let mut order_instruments = Vec::with_capacity(self.invest_assets.len() + 1);
+        order_instruments.push(self.instrument.clone());
 
         for asset in self.invest_assets.iter() {
-            let instrument = BidAsk::get_instrument_symbol(&asset.symbol, &self.base_asset);
-            instruments.push(instrument);
+            if let Some(instrument) = instruments.find_pair(&asset.symbol, &self.base_asset) {
+                order_instruments.push(instrument.symbol.clone());
+                continue;
+            }
+
+            // asset is priced by cross rate so every leg can change its price
+            for instrument in instruments.find_cross_pairs(&asset.symbol, &self.base_asset) {
+                if !order_instruments.contains(&instrument.symbol) {
+                    order_instruments.push(instrument.symbol.clone());
+                }
+            }
         }
 
-        instruments
+        order_instruments
     }
 
     pub fn get_type(&self) -> OrderType {
-        if self.desire_price.is_some() {
-            OrderType::Limit
-        } else {
-            OrderType::Market
+        self.order_type.clone()
+    }
+
+    pub fn get_expiration_date(&self) -> Option<DateTimeAsMicroseconds> {
+        self.time_in_force.get_expiration_date(self.created_date)
+    }
+
+    /// Checks that prices required by order type are set
+    pub fn validate_type(&self) -> Result<(), TradingError> {
+        let mut violations = Vec::new();
+        self.add_type_violations(&mut violations);
+
+        if !violations.is_empty() {
+            return Err(TradingError::InvalidOrder(violations));
+        }
+
+        Ok(())
+    }
+
+    /// Checks order against current quote and returns all found violations
+    pub fn validate(
+        &self,
+        bidask: &BidAsk,
+        asset_prices: &SortedVec<AssetSymbol, AssetPrice>,
+        max_leverage: Option<f64>,
+        instruments: &InstrumentsRegistry,
+    ) -> Result<(), Vec<OrderViolation>> {
+        let mut violations = Vec::new();
+
+        if bidask.instrument != self.instrument {
+            violations.push(OrderViolation::InvalidInstrument(bidask.instrument.clone()));
+        }
+
+        let spec = instruments.get_spec(&self.instrument);
+        let max_leverage = match (max_leverage, spec.and_then(|spec| spec.max_leverage)) {
+            (Some(max_leverage), Some(spec_max_leverage)) => Some(max_leverage.min(spec_max_leverage)),
+            (max_leverage, spec_max_leverage) => max_leverage.or(spec_max_leverage),
+        };
+        let is_leverage_valid = self.leverage.is_finite()
+            && self.leverage > 0.0
+            && max_leverage.map(|max| self.leverage <= max).unwrap_or(true);
+
+        if !is_leverage_valid {
+            violations.push(OrderViolation::InvalidLeverage {
+                leverage: self.leverage,
+                max_leverage,
+            });
+        }
+
+        if self.stop_out_percent <= self.margin_call_percent {
+            violations.push(OrderViolation::StopOutNotAboveMarginCall {
+                stop_out_percent: self.stop_out_percent,
+                margin_call_percent: self.margin_call_percent,
+            });
+        }
+
+        if self.top_up_enabled && self.top_up_percent >= self.stop_out_percent {
+            violations.push(OrderViolation::TopUpNotBelowStopOut {
+                top_up_percent: self.top_up_percent,
+                stop_out_percent: self.stop_out_percent,
+            });
+        }
+
+        if self.invest_assets.is_empty() {
+            violations.push(OrderViolation::EmptyInvestAssets);
+        }
+
+        for item in self.invest_assets.iter() {
+            if !item.amount.is_finite() || item.amount <= 0.0 {
+                violations.push(OrderViolation::InvalidInvestAmount {
+                    symbol: item.symbol.clone(),
+                    amount: item.amount,
+                });
+            }
+
+            if !asset_prices.contains(&item.symbol) {
+                violations.push(OrderViolation::MissingPrice(item.symbol.clone()));
+            }
+        }
+
+        if let (Some(spec), Ok(invest_amount)) = (
+            spec,
+            try_calculate_total_amount(&self.invest_assets, asset_prices),
+        ) {
+            let volume = self.calculate_instrument_volume(invest_amount, bidask.get_open_price(&self.side));
+
+            if let Err(violation) = validate_volume(spec, volume) {
+                violations.push(violation);
+            }
+        }
+
+        self.add_type_violations(&mut violations);
+        self.add_price_side_violations(bidask, &mut violations);
+        let close_price = bidask.get_close_price(&self.side);
+
+        if let Some(take_profit) = self.take_profit.as_ref() {
+            let is_profit_side = match self.side {
+                OrderSide::Buy => take_profit.value > close_price,
+                OrderSide::Sell => take_profit.value < close_price,
+            };
+
+            if take_profit.unit == AutoClosePositionUnit::PriceRateUnit && !is_profit_side {
+                violations.push(OrderViolation::InvalidTakeProfit {
+                    value: take_profit.value,
+                    close_price,
+                });
+            }
+
+            if matches!(
+                take_profit.unit,
+                AutoClosePositionUnit::TrailingPriceUnit | AutoClosePositionUnit::TrailingPercentUnit
+            ) {
+                violations.push(OrderViolation::UnsupportedTakeProfitUnit(take_profit.unit.clone()));
+            }
+        }
+
+        if let Some(stop_loss) = self.stop_loss.as_ref() {
+            let is_loss_side = match self.side {
+                OrderSide::Buy => stop_loss.value < close_price,
+                OrderSide::Sell => stop_loss.value > close_price,
+            };
+
+            if stop_loss.unit == AutoClosePositionUnit::PriceRateUnit && !is_loss_side {
+                violations.push(OrderViolation::InvalidStopLoss {
+                    value: stop_loss.value,
+                    close_price,
+                });
+            }
+
+            if stop_loss.is_trailing() && (!stop_loss.value.is_finite() || stop_loss.value <= 0.0) {
+                violations.push(OrderViolation::InvalidTrailingDistance(stop_loss.value));
+            }
+
+            if let Some(profit) = stop_loss.break_even_profit {
+                if !profit.is_finite() || profit <= 0.0 {
+                    violations.push(OrderViolation::InvalidBreakEvenProfit(profit));
+                }
+            }
+        }
+
+        if !violations.is_empty() {
+            return Err(violations);
+        }
+
+        Ok(())
+    }
+
+    fn add_type_violations(&self, violations: &mut Vec<OrderViolation>) {
+        if matches!(self.order_type, OrderType::Limit | OrderType::StopLimit) {
+            match self.desire_price {
+                None => violations.push(OrderViolation::MissingDesirePrice),
+                Some(price) if !price.is_finite() || price <= 0.0 => {
+                    violations.push(OrderViolation::InvalidDesirePrice(price))
+                }
+                _ => {}
+            }
+        }
+
+        if matches!(self.order_type, OrderType::Stop | OrderType::StopLimit) {
+            match self.stop_price {
+                None => violations.push(OrderViolation::MissingStopPrice),
+                Some(price) if !price.is_finite() || price <= 0.0 => {
+                    violations.push(OrderViolation::InvalidStopPrice(price))
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Checks desire and stop prices against open price of quote and each other
+    fn add_price_side_violations(&self, bidask: &BidAsk, violations: &mut Vec<OrderViolation>) {
+        let open_price = bidask.get_open_price(&self.side);
+        let is_above = |price: f64, other_price: f64| match self.side {
+            OrderSide::Buy => price > other_price,
+            OrderSide::Sell => price < other_price,
+        };
+        let desire_price = self.desire_price.filter(|price| price.is_finite() && *price > 0.0);
+        let stop_price = self.stop_price.filter(|price| price.is_finite() && *price > 0.0);
+
+        match (&self.order_type, desire_price, stop_price) {
+            (OrderType::Limit, Some(desire_price), _) if is_above(desire_price, open_price) => {
+                violations.push(OrderViolation::InvalidDesirePriceSide {
+                    desire_price,
+                    open_price,
+                });
+            }
+            (OrderType::Stop | OrderType::StopLimit, _, Some(stop_price)) => {
+                if !is_above(stop_price, open_price) {
+                    violations.push(OrderViolation::InvalidStopPriceSide {
+                        stop_price,
+                        open_price,
+                    });
+                }
+
+                // limit order of triggered stop-limit waits for price to return from stop price
+                if let Some(desire_price) = desire_price.filter(|_| self.order_type == OrderType::StopLimit) {
+                    if is_above(desire_price, stop_price) {
+                        violations.push(OrderViolation::InvalidStopLimitPrice {
+                            stop_price,
+                            desire_price,
+                        });
+                    }
+                }
+            }
+            _ => {}
         }
     }
 
@@ -129,21 +504,26 @@ impl Order {
         Uuid::new_v4().to_string()
     }
 
-    pub fn validate_prices(&self, asset_prices: &SortedVec<AssetSymbol, AssetPrice>) -> Result<(), String> {
+    pub fn validate_prices(&self, asset_prices: &SortedVec<AssetSymbol, AssetPrice>) -> Result<(), TradingError> {
         for item in self.invest_assets.iter() {
             let price = asset_prices.get(&item.symbol);
 
             if price.is_none() {
-                let message = format!("Not Found price for {}", item.symbol);
-                return Err(message);
+                return Err(TradingError::MissingPrice(item.symbol.to_string()));
             }
         }
 
         Ok(())
     }
 
-    pub fn open(self, bidask: &BidAsk, asset_prices: &SortedVec<AssetSymbol, AssetPrice>) -> Position {
-        self.open_with_id(Position::generate_id(), bidask, asset_prices)
+    /// Opens position with open and activate dates of context clock time
+    pub fn open(
+        self,
+        bidask: &BidAsk,
+        asset_prices: &SortedVec<AssetSymbol, AssetPrice>,
+        context: &OpenContext,
+    ) -> Position {
+        self.open_with_id(Position::generate_id(), bidask, asset_prices, context)
     }
 
     pub fn open_with_id(
@@ -151,57 +531,230 @@ impl Order {
         id: PositionId,
         bidask: &BidAsk,
         asset_prices: &SortedVec<AssetSymbol, AssetPrice>,
+        context: &OpenContext,
     ) -> Position {
-        if self.validate_prices(asset_prices).is_err() {
-            panic!("Can't open order: invalid prices");
+        self.try_open_position(id, bidask, asset_prices, context)
+            .unwrap_or_else(|err| panic!("Can't open order: {}", err))
+    }
+
+    /// Opens position on quote. Client and raw quotes, order book and trading parameters
+    /// are taken from context
+    pub fn try_open(
+        self,
+        bidask: &BidAsk,
+        asset_prices: &SortedVec<AssetSymbol, AssetPrice>,
+        context: &OpenContext,
+    ) -> Result<Position, TradingError> {
+        self.try_open_position(Position::generate_id(), bidask, asset_prices, context)
+    }
+
+    /// Opens position with instrument spec resolved from registry
+    fn try_open_position(
+        mut self,
+        id: PositionId,
+        bidask: &BidAsk,
+        asset_prices: &SortedVec<AssetSymbol, AssetPrice>,
+        context: &OpenContext,
+    ) -> Result<Position, TradingError> {
+        self.validate_prices(asset_prices)?;
+        self.validate_type()?;
+
+        if self.reduce_only {
+            return Err(TradingError::InvalidOrder(vec![OrderViolation::ReduceOnly]));
+        }
+
+        if self.leverage.is_nan() || self.leverage <= 0.0 {
+            return Err(TradingError::InvalidLeverage(self.leverage));
+        }
+
+        let raw_bidask = context.raw_bidask.unwrap_or(bidask);
+        let client_bidask = context
+            .markups
+            .map(|markups| markups.apply(self.trader_group.as_deref(), bidask));
+        let bidask = client_bidask.as_ref().unwrap_or(bidask);
+
+        if bidask.instrument != self.instrument || raw_bidask.instrument != self.instrument {
+            return Err(TradingError::InvalidInstrument(format!(
+                "BidAsk instrument {} must be {}",
+                bidask.instrument, self.instrument
+            )));
         }
 
-        if self.leverage <= 0.0 {
-            panic!("Can't open order: leverage can't be less or equals zero");
+        let order_book = context.order_book;
+        let clock = context.clock;
+        let spec = context.instruments.get_spec(&self.instrument).cloned();
+        let commission_schedule = context
+            .commissions
+            .get(self.trader_group.as_deref(), &self.instrument)
+            .cloned();
+
+        if let Some(spec) = spec.as_ref() {
+            if spec.max_leverage.map(|max| self.leverage > max).unwrap_or(false) {
+                return Err(TradingError::InvalidLeverage(self.leverage));
+            }
+
+            let mut prices = asset_prices.to_owned();
+            prices.insert_or_replace(AssetPrice {price: 1.0, symbol: self.base_asset.clone()});
+            let volume = self.calculate_instrument_volume(
+                calculate_total_amount(&self.invest_assets, &prices),
+                bidask.get_open_price(&self.side),
+            );
+
+            if let Err(violation) = validate_volume(spec, volume) {
+                return Err(TradingError::InvalidOrder(vec![violation]));
+            }
+
+            self.round_prices(spec);
         }
 
-        match self.get_type() {
+        let position = match self.get_type() {
             OrderType::Market => {
-                let position = self.into_active(id, bidask, asset_prices);
+                let position = self.into_active(
+                    id,
+                    bidask,
+                    raw_bidask,
+                    order_book,
+                    asset_prices,
+                    spec,
+                    commission_schedule,
+                    clock.now(),
+                );
                 Position::Active(position)
             }
-            OrderType::Limit => {
-                let position = self.into_pending(id, bidask, asset_prices);
-                position.try_activate()
+            OrderType::Limit | OrderType::Stop | OrderType::StopLimit => {
+                let position = self.into_pending(
+                    id,
+                    bidask,
+                    raw_bidask,
+                    asset_prices,
+                    spec,
+                    commission_schedule,
+                    clock.now(),
+                );
+
+                if position.can_activate(order_book) {
+                    Position::Active(position.activate(order_book, clock)?)
+                } else {
+                    Position::Pending(position)
+                }
             }
-        }
+        };
+
+        Ok(position)
+    }
+
+    /// Calculates liquidation price of position as if order is opened at market by quote
+    pub fn preview_liquidation_price(
+        &self,
+        bidask: &BidAsk,
+        asset_prices: &SortedVec<AssetSymbol, AssetPrice>,
+        instruments: &InstrumentsRegistry,
+        commissions: &CommissionSchedules,
+    ) -> Result<Option<f64>, TradingError> {
+        let mut order = self.clone();
+        order.order_type = OrderType::Market;
+
+        // preview is dated by the quote
+        let clock = QuoteClock::new(bidask.datetime);
+        let context = OpenContext::new(instruments, commissions, &clock);
+        let Position::Active(position) = order.try_open(bidask, asset_prices, &context)? else {
+            return Err(TradingError::PositionStateMismatch(
+                "Market order position is not active".to_string(),
+            ));
+        };
+
+        Ok(position.get_liquidation_price())
     }
 
     pub fn calculate_volume(&self, invest_amount: f64) -> f64 {
         invest_amount * self.leverage
     }
 
+    /// Calculates volume in instrument base asset of invested amount at instrument price
+    pub fn calculate_instrument_volume(&self, invest_amount: f64, instrument_price: f64) -> f64 {
+        self.calculate_volume(invest_amount) / instrument_price
+    }
+
+    /// Rounds desire, stop and auto close prices to tick size of instrument spec
+    fn round_prices(&mut self, spec: &InstrumentSpec) {
+        self.desire_price = self.desire_price.map(|price| spec.round_price(price));
+        self.stop_price = self.stop_price.map(|price| spec.round_price(price));
+
+        if let Some(take_profit) = self.take_profit.as_mut() {
+            take_profit.value = round_auto_close_value(spec, take_profit.value, &take_profit.unit);
+        }
+
+        if let Some(stop_loss) = self.stop_loss.as_mut() {
+            stop_loss.value = round_auto_close_value(spec, stop_loss.value, &stop_loss.unit);
+        }
+    }
+
     pub fn calculate_invest_amount(&self, asset_prices: &SortedVec<AssetSymbol, AssetPrice>) -> f64 {
         calculate_total_amount(&self.invest_assets, asset_prices)
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn into_active(
         self,
         id: PositionId,
         bid_ask: &BidAsk,
+        raw_bid_ask: &BidAsk,
+        order_book: Option<&OrderBook>,
         asset_prices: &SortedVec<AssetSymbol, AssetPrice>,
+        instrument_spec: Option<InstrumentSpec>,
+        commission_schedule: Option<CommissionSchedule>,
+        now: DateTimeAsMicroseconds,
     ) -> ActivePosition {
-        let now = DateTimeAsMicroseconds::now();
         let mut asset_prices = asset_prices.to_owned();
         asset_prices.insert_or_replace(AssetPrice {price: 1.0, symbol: self.base_asset.clone()});
+        let raw_price = raw_bid_ask.get_open_price(&self.side);
+        let depth_prices = order_book.and_then(|order_book| {
+            let invest_amount = calculate_total_amount(&self.invest_assets, &asset_prices);
+            let mut volume = self.calculate_instrument_volume(invest_amount, raw_price);
+
+            if let Some(spec) = instrument_spec.as_ref() {
+                volume = spec.floor_volume(volume);
+            }
+
+            order_book.get_client_open_price(&self.side, volume, bid_ask.get_open_price(&self.side), raw_price)
+        });
+        let (open_price, open_raw_price) = match depth_prices {
+            Some((price, depth_raw_price)) => {
+                let price = match instrument_spec.as_ref() {
+                    Some(spec) => spec.round_price(price),
+                    None => price,
+                };
+
+                (price, depth_raw_price)
+            }
+            None => (bid_ask.get_open_price(&self.side), raw_price),
+        };
 
-        ActivePosition {
+        let mut position = ActivePosition {
             id,
             open_date: now,
-            open_price: bid_ask.get_open_price(&self.side),
+            open_price,
             open_asset_prices: asset_prices.clone(),
-            activate_price: bid_ask.get_open_price(&self.side),
+            activate_price: open_price,
             activate_date: now,
             activate_asset_prices: asset_prices.clone(),
             current_price: bid_ask.get_close_price(&self.side),
+            valuation_price: bid_ask.get_close_price(&self.side),
+            stop_loss_price: None,
+            is_break_even_reached: false,
+            break_even_price: None,
             current_asset_prices: asset_prices,
+            open_raw_price,
+            activate_raw_price: open_raw_price,
+            current_raw_price: raw_bid_ask.get_close_price(&self.side),
             last_update_date: now,
             top_ups: Vec::new(),
+            increases: Vec::new(),
+            commissions: Vec::new(),
+            funding_pnl: 0.0,
+            last_funding_date: now,
+            swap_pnl: 0.0,
+            last_swap_date: now,
             current_pnl: 0.0,
             current_loss_percent: 0.0,
             prev_loss_percent: 0.0,
@@ -209,16 +762,27 @@ impl Order {
             total_invest_assets: self.invest_assets.clone(),
             order: self,
             bonus_invest_assets: SortedVec::new_with_capacity(0),
-        }
+            instrument_spec,
+            commission_schedule,
+        };
+        position.commissions.extend(position.calculate_order_commission());
+        position.update_stop_loss_price();
+        position.update_pnl();
+
+        position
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn into_pending(
         self,
         id: PositionId,
         bidask: &BidAsk,
+        raw_bidask: &BidAsk,
         asset_prices: &SortedVec<AssetSymbol, AssetPrice>,
+        instrument_spec: Option<InstrumentSpec>,
+        commission_schedule: Option<CommissionSchedule>,
+        now: DateTimeAsMicroseconds,
     ) -> PendingPosition {
-        let now = DateTimeAsMicroseconds::now();
         let mut asset_prices = asset_prices.to_owned();
         asset_prices.insert_or_replace(AssetPrice {price: 1.0, symbol: self.base_asset.clone()});
 
@@ -229,9 +793,38 @@ impl Order {
             open_asset_prices: asset_prices.clone(),
             current_asset_prices: asset_prices,
             current_price: bidask.get_open_price(&self.side),
+            open_raw_price: raw_bidask.get_open_price(&self.side),
+            current_raw_price: raw_bidask.get_open_price(&self.side),
             last_update_date: now,
+            is_stop_triggered: false,
             order: self,
             total_invest_assets: SortedVec::new(),
+            instrument_spec,
+            commission_schedule,
+        }
+    }
+}
+
+/// Checks volume in instrument base asset against volume limits of instrument spec
+fn validate_volume(spec: &InstrumentSpec, volume: f64) -> Result<(), OrderViolation> {
+    if !spec.is_volume_valid(volume) {
+        return Err(OrderViolation::InvalidVolume {
+            volume,
+            min_volume: spec.min_volume,
+            max_volume: spec.max_volume,
+        });
+    }
+
+    Ok(())
+}
+
+/// Rounds price values of take profit or stop loss config to tick and amount values to pnl accuracy
+fn round_auto_close_value(spec: &InstrumentSpec, value: f64, unit: &AutoClosePositionUnit) -> f64 {
+    match unit {
+        AutoClosePositionUnit::PriceRateUnit | AutoClosePositionUnit::TrailingPriceUnit => {
+            spec.round_price(value)
         }
+        AutoClosePositionUnit::AssetAmountUnit => spec.round_amount(value),
+        AutoClosePositionUnit::TrailingPercentUnit => value,
     }
 }
@@ -0,0 +1,64 @@
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+use crate::position_id::PositionId;
+
+#[derive(Debug, Clone, PartialEq, IntoPrimitive, TryFromPrimitive)]
+#[repr(i32)]
+pub enum PositionGroupType {
+    /// Activation of one pending position cancels the other pending positions
+    OneCancelsOther = 0,
+    /// Opposite exits reduce entry after its activation. Fill of one exit cancels the others
+    Bracket = 1,
+}
+
+/// Pending positions linked by activation rules
+#[derive(Debug, Clone)]
+pub struct PositionGroup {
+    pub id: String,
+    pub group_type: PositionGroupType,
+    /// Entry of bracket group. None for one-cancels-other group
+    pub entry_id: Option<PositionId>,
+    /// Members of one-cancels-other group or exits of bracket group
+    pub ids: Vec<PositionId>,
+    /// Bracket entry is activated so exits can be filled
+    pub is_entry_active: bool,
+    /// Group rule is applied. Members left in group were locked and are canceled once unlocked
+    pub is_canceled: bool,
+}
+
+impl PositionGroup {
+    pub fn new_one_cancels_other(id: impl Into<String>, ids: Vec<PositionId>) -> Self {
+        Self {
+            id: id.into(),
+            group_type: PositionGroupType::OneCancelsOther,
+            entry_id: None,
+            ids,
+            is_entry_active: false,
+            is_canceled: false,
+        }
+    }
+
+    pub fn new_bracket(id: impl Into<String>, entry_id: PositionId, exit_ids: Vec<PositionId>) -> Self {
+        Self {
+            id: id.into(),
+            group_type: PositionGroupType::Bracket,
+            entry_id: Some(entry_id),
+            ids: exit_ids,
+            is_entry_active: false,
+            is_canceled: false,
+        }
+    }
+
+    pub fn is_entry(&self, id: &PositionId) -> bool {
+        self.entry_id.as_ref() == Some(id)
+    }
+
+    /// Returns entry and members ids
+    pub fn get_all_ids(&self) -> impl Iterator<Item = &PositionId> {
+        self.entry_id.iter().chain(self.ids.iter())
+    }
+
+    /// Returns true if pending position can't be activated or filled yet because its bracket entry isn't active
+    pub fn is_suspended(&self, id: &PositionId) -> bool {
+        self.group_type == PositionGroupType::Bracket && !self.is_entry_active && !self.is_entry(id)
+    }
+}
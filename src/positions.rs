@@ -1,16 +1,24 @@
-use crate::calculations::{calculate_percent, floor};
+use crate::calculations::{calculate_percent, floor, try_calculate_total_amount};
+use crate::errors::TradingError;
 use crate::top_ups::{ActiveTopUp, CanceledTopUp};
-use crate::{assets, calculations::calculate_total_amount, orders::{Order, OrderSide, StopLossConfig, TakeProfitConfig}};
+use crate::{assets, calculations::calculate_total_amount, orders::{AutoClosePositionUnit, OpenContext, Order, OrderSide, OrderType, StopLossConfig, TakeProfitConfig, TimeInForce}};
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 use rust_extensions::date_time::DateTimeAsMicroseconds;
 use std::time::Duration;
-use compact_str::CompactString;
 use rust_extensions::sorted_vec::SortedVec;
 use uuid::Uuid;
 use crate::asset_symbol::AssetSymbol;
 use crate::assets::{AssetAmount, AssetPrice};
 use crate::instrument_symbol::InstrumentSymbol;
+use crate::instruments::{InstrumentSpec, InstrumentsRegistry};
+use crate::order_books::OrderBook;
+use crate::clock::Clock;
+use crate::commissions::{CommissionSchedule, CommissionSchedules, CommissionType, PositionCommission};
+use crate::fundings::FundingRates;
+use crate::swaps::SwapRates;
+use crate::increases::PositionIncrease;
 use crate::position_id::PositionId;
+use crate::valuations::ValuationPrices;
 
 #[derive(Debug, Clone, IntoPrimitive, TryFromPrimitive)]
 #[repr(i32)]
@@ -21,6 +29,10 @@ pub enum ClosePositionReason {
     StopLoss = 3,
     AdminCommand = 4,
     InsufficientBalance = 5,
+    /// Pending position reached expiration date of order time in force
+    Expired = 6,
+    /// Pending position was canceled by activation or close of position in the same group
+    GroupCanceled = 7,
 }
 
 #[derive(Clone, Debug)]
@@ -41,12 +53,13 @@ impl BidAsk {
         }
     }
 
-    pub fn get_instrument_symbol(base_asset: &AssetSymbol, quote_asset: &AssetSymbol) -> InstrumentSymbol {
-        let mut compact_str = CompactString::with_capacity(base_asset.len() + quote_asset.len());
-        compact_str.push_str(base_asset);
-        compact_str.push_str(quote_asset);
-
-        compact_str.into()
+    /// Returns registered instrument symbol for base and quote assets pair
+    pub fn get_instrument_symbol(
+        instruments: &InstrumentsRegistry,
+        base_asset: &AssetSymbol,
+        quote_asset: &AssetSymbol,
+    ) -> Option<InstrumentSymbol> {
+        instruments.find_symbol(base_asset, quote_asset).cloned()
     }
 
     pub fn get_close_price(&self, side: &OrderSide) -> f64 {
@@ -63,23 +76,54 @@ impl BidAsk {
         }
     }
 
-    pub fn get_asset_price(&self, asset: &AssetSymbol, side: &OrderSide) -> f64 {
-        match side {
-            OrderSide::Sell => {
-                if self.instrument.0.starts_with(asset.0.as_str()) {
-                    self.ask
-                } else {
-                    panic!("Invalid instrument {} for asset {}", self.instrument, asset)
-                }
-            }
-            OrderSide::Buy => {
-                if self.instrument.0.starts_with(asset.0.as_str()) {
-                    self.bid
-                } else {
-                    panic!("Invalid instrument {} for asset {}", self.instrument, asset)
-                }
+    /// Returns price of asset in the other asset of instrument, inverted if asset is quote asset
+    pub fn get_asset_price(
+        &self,
+        instruments: &InstrumentsRegistry,
+        asset: &AssetSymbol,
+        side: &OrderSide,
+    ) -> f64 {
+        self.try_get_asset_price(instruments, asset, side)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_get_asset_price(
+        &self,
+        instruments: &InstrumentsRegistry,
+        asset: &AssetSymbol,
+        side: &OrderSide,
+    ) -> Result<f64, TradingError> {
+        let Some(instrument) = instruments.get(&self.instrument) else {
+            return Err(TradingError::InvalidInstrument(format!(
+                "{} is not registered",
+                self.instrument
+            )));
+        };
+
+        if instrument.base_asset == *asset {
+            return match side {
+                OrderSide::Sell => Ok(self.ask),
+                OrderSide::Buy => Ok(self.bid),
+            };
+        }
+
+        if instrument.quote_asset == *asset {
+            let price = match side {
+                OrderSide::Sell => self.bid,
+                OrderSide::Buy => self.ask,
+            };
+
+            if !price.is_finite() || price <= 0.0 {
+                return Err(TradingError::MissingPrice(asset.to_string()));
             }
+
+            return Ok(1.0 / price);
         }
+
+        Err(TradingError::InvalidInstrument(format!(
+            "{} for asset {}",
+            self.instrument, asset
+        )))
     }
 }
 
@@ -127,6 +171,15 @@ impl Position {
         }
     }
 
+    /// Returns liquidation price of active position
+    pub fn get_liquidation_price(&self) -> Option<f64> {
+        match self {
+            Position::Active(position) => position.get_liquidation_price(),
+            Position::Closed(_) => None,
+            Position::Pending(_) => None,
+        }
+    }
+
     pub fn get_status(&self) -> PositionStatus {
         match self {
             Position::Pending(_position) => PositionStatus::Pending,
@@ -135,40 +188,46 @@ impl Position {
         }
     }
 
-    pub fn get_instruments(&self) -> Vec<InstrumentSymbol> {
+    pub fn get_instruments(&self, instruments: &InstrumentsRegistry) -> Vec<InstrumentSymbol> {
         match self {
-            Position::Pending(position) => position.order.get_instruments().into_iter().collect(),
+            Position::Pending(position) => position.order.get_instruments(instruments).into_iter().collect(),
             Position::Active(position) => {
-                let order_instruments = position.order.get_instruments();
-                let mut instruments = self.get_top_up_instruments(&position.top_ups);
-                instruments.extend(order_instruments.into_iter());
+                let order_instruments = position.order.get_instruments(instruments);
+                let mut top_up_instruments = self.get_top_up_instruments(&position.top_ups, instruments);
+                top_up_instruments.extend(order_instruments.into_iter());
 
-                instruments
+                top_up_instruments
             }
             Position::Closed(position) => {
-                let order_instruments = position.order.get_instruments();
-                let mut instruments = self.get_top_up_instruments(&position.top_ups);
-                instruments.extend(order_instruments.into_iter());
+                let order_instruments = position.order.get_instruments(instruments);
+                let mut top_up_instruments = self.get_top_up_instruments(&position.top_ups, instruments);
+                top_up_instruments.extend(order_instruments.into_iter());
 
-                instruments
+                top_up_instruments
             }
         }
     }
 
-    fn get_top_up_instruments(&self, top_ups: &Vec<ActiveTopUp>) -> Vec<InstrumentSymbol> {
-        let mut instruments = Vec::with_capacity(10);
+    fn get_top_up_instruments(
+        &self,
+        top_ups: &Vec<ActiveTopUp>,
+        instruments: &InstrumentsRegistry,
+    ) -> Vec<InstrumentSymbol> {
+        let mut top_up_instruments = Vec::with_capacity(10);
 
         for top_up in top_ups {
             for item in top_up.total_assets.iter() {
-                let instrument = BidAsk::get_instrument_symbol(&item.symbol, &self.get_order().base_asset);
+                let instrument = instruments.find_pair(&item.symbol, &self.get_order().base_asset);
 
-                if !instruments.contains(&instrument) {
-                    instruments.push(instrument);
+                if let Some(instrument) = instrument {
+                    if !top_up_instruments.contains(&instrument.symbol) {
+                        top_up_instruments.push(instrument.symbol.clone());
+                    }
                 }
             }
         }
 
-        instruments
+        top_up_instruments
     }
 }
 
@@ -190,117 +249,166 @@ pub struct PendingPosition {
     pub open_asset_prices: SortedVec<AssetSymbol, AssetPrice>,
     pub current_price: f64,
     pub current_asset_prices: SortedVec<AssetSymbol, AssetPrice>,
+    /// Open price by raw quote before markup
+    pub open_raw_price: f64,
+    /// Current price by raw quote before markup
+    pub current_raw_price: f64,
     pub last_update_date: DateTimeAsMicroseconds,
+    /// Stop price of stop-limit order was reached and position waits for desire price
+    pub is_stop_triggered: bool,
     pub total_invest_assets: SortedVec<AssetSymbol, AssetAmount>,
+    /// Trading parameters of instrument resolved from registry at open
+    pub instrument_spec: Option<InstrumentSpec>,
+    /// Commission schedule of order trader group resolved at open
+    pub commission_schedule: Option<CommissionSchedule>,
 }
 
 impl PendingPosition {
-    pub fn update(&mut self, bidask: &BidAsk) {
+    pub fn update(&mut self, bidask: &BidAsk, instruments: &InstrumentsRegistry) {
         self.update_instrument_price(bidask);
-        self.update_asset_prices(bidask);
-        self.last_update_date = DateTimeAsMicroseconds::now();
+        self.update_asset_prices(bidask, instruments);
+        self.last_update_date = bidask.datetime;
+    }
+
+    pub fn is_expired(&self, now: DateTimeAsMicroseconds) -> bool {
+        self.order
+            .get_expiration_date()
+            .map(|date| now.unix_microseconds >= date.unix_microseconds)
+            .unwrap_or(false)
     }
 
     pub fn is_price_reached(&self) -> bool {
+        match self.order.order_type {
+            OrderType::Market => true,
+            OrderType::Limit => self.is_desire_price_reached(),
+            OrderType::Stop => self.is_stop_price_reached(),
+            OrderType::StopLimit => {
+                (self.is_stop_triggered || self.is_stop_price_reached())
+                    && self.is_desire_price_reached()
+            }
+        }
+    }
+
+    fn is_desire_price_reached(&self) -> bool {
         let Some(desired_price) = self.order.desire_price else {
             panic!("PendingPosition without desire price");
         };
 
-        let is_limit_sell = self.order.side == OrderSide::Sell && self.open_price <= desired_price;
-
-        if is_limit_sell && self.current_price >= desired_price {
-            return true;
+        match self.order.side {
+            OrderSide::Buy => self.current_price <= desired_price,
+            OrderSide::Sell => self.current_price >= desired_price,
         }
+    }
 
-        let is_limit_buy = self.order.side == OrderSide::Buy && self.open_price >= desired_price;
+    fn is_stop_price_reached(&self) -> bool {
+        let Some(stop_price) = self.order.stop_price else {
+            panic!("PendingPosition without stop price");
+        };
 
-        if is_limit_buy && self.current_price <= desired_price {
-            return true;
+        match self.order.side {
+            OrderSide::Buy => self.current_price >= stop_price,
+            OrderSide::Sell => self.current_price <= stop_price,
         }
+    }
 
-        let is_stop_sell = self.order.side == OrderSide::Sell && self.open_price >= desired_price;
-
-        if is_stop_sell && self.current_price <= desired_price {
-            return true;
+    fn update_instrument_price(&mut self, bidask: &BidAsk) {
+        if self.order.instrument != bidask.instrument {
+            return;
         }
 
-        let is_stop_buy = self.order.side == OrderSide::Buy && self.open_price <= desired_price;
+        self.current_price = bidask.get_open_price(&self.order.side);
 
-        if is_stop_buy && self.current_price >= desired_price {
-            return true;
+        if self.order.order_type == OrderType::StopLimit
+            && !self.is_stop_triggered
+            && self.is_stop_price_reached()
+        {
+            self.is_stop_triggered = true;
         }
-
-        false
     }
 
-    fn update_instrument_price(&mut self, bidask: &BidAsk) {
-        if self.order.instrument == bidask.instrument {
-            self.current_price = bidask.get_open_price(&self.order.side)
+    /// Updates price by raw quote. Must be called after update with client quote
+    pub fn update_raw_price(&mut self, raw_bidask: &BidAsk) {
+        if self.order.instrument == raw_bidask.instrument {
+            self.current_raw_price = raw_bidask.get_open_price(&self.order.side)
         }
     }
 
-    fn update_asset_prices(&mut self, bidask: &BidAsk) {
-        for asset in self.order.invest_assets.iter() {
-            let id = BidAsk::get_instrument_symbol(&asset.symbol, &self.order.base_asset);
+    fn update_asset_prices(&mut self, bidask: &BidAsk, instruments: &InstrumentsRegistry) {
+        let Some(instrument) = instruments.get(&bidask.instrument) else {
+            return;
+        };
 
-            if id == bidask.instrument {
-                let price = bidask.get_asset_price(&asset.symbol, &OrderSide::Sell);
-                let current_asset_price = self.current_asset_prices.get_mut(&asset.symbol);
+        let Some(asset) = instrument.get_counter_asset(&self.order.base_asset) else {
+            return;
+        };
 
-                if let Some(current_asset_price) = current_asset_price {
-                    current_asset_price.price = price;
-                } else {
-                    self.current_asset_prices.insert_or_replace(AssetPrice::new(asset.symbol.clone(), price));
-                }
-            }
+        if !self.order.invest_assets.contains(asset) {
+            return;
         }
-    }
 
-    pub fn can_activate(&self) -> bool {
-        if self.total_invest_assets.is_empty() {
-            return false;
-        }
+        // quote without valid price keeps the last asset price
+        let Ok(price) = bidask.try_get_asset_price(instruments, asset, &OrderSide::Sell) else {
+            return;
+        };
+        let current_asset_price = self.current_asset_prices.get_mut(asset);
 
-        if !self.is_price_reached() {
-            return false;
+        if let Some(current_asset_price) = current_asset_price {
+            current_asset_price.price = price;
+        } else {
+            self.current_asset_prices.insert_or_replace(AssetPrice::new(asset.clone(), price));
         }
+    }
 
-        true
+    /// Returns true if position is activated by [`Self::activate`] with order book
+    pub fn can_activate(&self, order_book: Option<&OrderBook>) -> bool {
+        self.get_activate_prices(order_book).is_ok()
     }
 
-    pub fn try_activate(self) -> Position {
-        if self.can_activate() {
-            return Position::Active(self.activate().expect("checked in can_activate"));
+    pub fn try_activate(self, clock: &dyn Clock) -> Position {
+        if self.can_activate(None) {
+            return Position::Active(self.activate(None, clock).expect("checked in can_activate"));
         }
 
         Position::Pending(self)
     }
 
-    pub fn activate(self) -> Result<ActivePosition, String> {
-        if !self.is_price_reached() {
-            return Err("desire_price isn't reached".to_string());
-        }
-
-        if self.total_invest_assets.is_empty() {
-            return Err("total_invest_assets is empty".to_string());
-        }
-
-        let now = DateTimeAsMicroseconds::now();
+    /// Activates position at clock time. Activate price is VWAP price of raw order book
+    /// with markup of client price if order book is set
+    pub fn activate(
+        self,
+        order_book: Option<&OrderBook>,
+        clock: &dyn Clock,
+    ) -> Result<ActivePosition, TradingError> {
+        let (activate_price, activate_raw_price) = self.get_activate_prices(order_book)?;
+        let now = clock.now();
         let mut order = self.order;
         order.invest_assets = self.total_invest_assets;
 
-        Ok(ActivePosition {
+        let mut position = ActivePosition {
             id: self.id,
             open_price: self.open_price,
             open_date: self.open_date,
             open_asset_prices: self.open_asset_prices,
-            activate_price: self.current_price,
+            activate_price,
             activate_date: now,
             activate_asset_prices: self.current_asset_prices.to_owned(),
             current_price: self.current_price,
+            valuation_price: self.current_price,
+            stop_loss_price: None,
+            is_break_even_reached: false,
+            break_even_price: None,
             current_asset_prices: self.current_asset_prices,
+            open_raw_price: self.open_raw_price,
+            activate_raw_price,
+            current_raw_price: self.current_raw_price,
             last_update_date: now,
             top_ups: Vec::new(),
+            increases: Vec::new(),
+            commissions: Vec::new(),
+            funding_pnl: 0.0,
+            last_funding_date: now,
+            swap_pnl: 0.0,
+            last_swap_date: now,
             current_pnl: 0.0,
             current_loss_percent: 0.0,
             prev_loss_percent: 0.0,
@@ -308,7 +416,71 @@ impl PendingPosition {
             total_invest_assets: order.invest_assets.clone(),
             order,
             bonus_invest_assets: SortedVec::new(),
-        })
+            instrument_spec: self.instrument_spec,
+            commission_schedule: self.commission_schedule,
+        };
+        position.commissions.extend(position.calculate_order_commission());
+        position.update_stop_loss_price();
+        position.update_pnl();
+
+        Ok(position)
+    }
+
+    /// Returns client and raw activate prices. Prices are VWAP prices of order book if it is set
+    fn get_activate_prices(&self, order_book: Option<&OrderBook>) -> Result<(f64, f64), TradingError> {
+        if !self.is_price_reached() {
+            return Err(TradingError::PositionStateMismatch(
+                "desire_price isn't reached".to_string(),
+            ));
+        }
+
+        if self.total_invest_assets.is_empty() {
+            return Err(TradingError::PositionStateMismatch(
+                "total_invest_assets is empty".to_string(),
+            ));
+        }
+
+        let Some(order_book) = order_book else {
+            return Ok((self.current_price, self.current_raw_price));
+        };
+        let invest_amount = try_calculate_total_amount(&self.total_invest_assets, &self.current_asset_prices)?;
+        let mut volume = self.order.calculate_instrument_volume(invest_amount, self.current_raw_price);
+
+        if let Some(spec) = self.instrument_spec.as_ref() {
+            volume = spec.floor_volume(volume);
+        }
+
+        let prices = order_book.get_client_open_price(
+            &self.order.side,
+            volume,
+            self.current_price,
+            self.current_raw_price,
+        );
+        let Some((price, raw_price)) = prices else {
+            return Ok((self.current_price, self.current_raw_price));
+        };
+        let price = match self.instrument_spec.as_ref() {
+            Some(spec) => spec.round_price(price),
+            None => price,
+        };
+
+        // limit order isn't filled at price worse than desire price
+        if let OrderType::Limit | OrderType::StopLimit = self.order.order_type {
+            let desire_price = self.order.desire_price.expect("PendingPosition without desire price");
+            let is_worse = match self.order.side {
+                OrderSide::Buy => price > desire_price,
+                OrderSide::Sell => price < desire_price,
+            };
+
+            if is_worse {
+                return Err(TradingError::PositionStateMismatch(format!(
+                    "VWAP price {} is worse than desire price {}",
+                    price, desire_price
+                )));
+            }
+        }
+
+        Ok((price, raw_price))
     }
 
     pub fn set_take_profit(&mut self, value: Option<TakeProfitConfig>) {
@@ -323,16 +495,17 @@ impl PendingPosition {
         self.order.desire_price = Some(value);
     }
 
+    pub fn set_stop_price(&mut self, value: f64) {
+        self.order.stop_price = Some(value);
+    }
+
     pub fn add_invest_assets(
         &mut self,
         amounts_by_assets: &SortedVec<AssetSymbol, AssetAmount>,
-    ) -> Result<(), String> {
+    ) -> Result<(), TradingError> {
         for item in amounts_by_assets.iter() {
             if !self.open_asset_prices.contains(&item.symbol) {
-                return Err(format!(
-                    "Can't invest '{}': not found open price",
-                    &item.symbol
-                ));
+                return Err(TradingError::MissingPrice(item.symbol.to_string()));
             }
 
             let invested_asset_amount: Option<&mut AssetAmount> = self.total_invest_assets.get_mut(&item.symbol);
@@ -348,7 +521,7 @@ impl PendingPosition {
         Ok(())
     }
 
-    pub fn close(self, reason: ClosePositionReason) -> ClosedPosition {
+    pub fn close(self, reason: ClosePositionReason, clock: &dyn Clock) -> ClosedPosition {
         ClosedPosition {
             pnl: None,
             asset_pnls: SortedVec::new(),
@@ -358,12 +531,22 @@ impl PendingPosition {
             activate_date: None,
             activate_price: None,
             activate_asset_prices: SortedVec::new(),
-            close_date: DateTimeAsMicroseconds::now(),
+            close_date: clock.now(),
             close_price: self.current_price,
             close_reason: reason,
             close_asset_prices: self.current_asset_prices.to_owned(),
+            open_raw_price: self.open_raw_price,
+            activate_raw_price: None,
+            close_raw_price: self.current_raw_price,
+            stop_loss_price: None,
+            is_partial: false,
+            parent_id: None,
             id: self.id,
             top_ups: Vec::with_capacity(0),
+            increases: Vec::with_capacity(0),
+            commissions: Vec::with_capacity(0),
+            funding_pnl: 0.0,
+            swap_pnl: 0.0,
             total_invest_assets: self.total_invest_assets,
             order: self.order,
             invest_bonus_assets: SortedVec::new(),
@@ -382,15 +565,44 @@ pub struct ActivePosition {
     pub activate_date: DateTimeAsMicroseconds,
     pub activate_asset_prices: SortedVec<AssetSymbol, AssetPrice>,
     pub current_price: f64,
+    /// Price used for pnl, loss percent and stop-out
+    pub valuation_price: f64,
+    /// Current stop loss trigger price. Follows close price for trailing stop loss
+    pub stop_loss_price: Option<f64>,
+    /// Break-even profit of stop loss config is reached so stop loss price isn't below break-even
+    pub is_break_even_reached: bool,
+    /// Cached break-even price. Reset when top-ups, increases, commissions, funding or swaps change
+    pub break_even_price: Option<f64>,
     pub current_asset_prices: SortedVec<AssetSymbol, AssetPrice>,
+    /// Open price by raw quote before markup
+    pub open_raw_price: f64,
+    /// Activate price by raw quote before markup
+    pub activate_raw_price: f64,
+    /// Current price by raw quote before markup
+    pub current_raw_price: f64,
     pub last_update_date: DateTimeAsMicroseconds,
     pub top_ups: Vec<ActiveTopUp>,
+    pub increases: Vec<PositionIncrease>,
+    /// Commissions charged on open and increases. Deducted from pnl of invested assets
+    pub commissions: Vec<PositionCommission>,
+    /// Funding fees in base asset charged or received by position. Included in current pnl
+    pub funding_pnl: f64,
+    /// Date up to which funding periods are charged
+    pub last_funding_date: DateTimeAsMicroseconds,
+    /// Overnight swaps in base asset charged or received by position. Included in current pnl
+    pub swap_pnl: f64,
+    /// Date up to which rollovers are charged
+    pub last_swap_date: DateTimeAsMicroseconds,
     pub current_pnl: f64,
     pub current_loss_percent: f64,
     pub prev_loss_percent: f64,
     pub top_up_locked: bool,
     pub total_invest_assets: SortedVec<AssetSymbol, AssetAmount>,
     pub bonus_invest_assets: SortedVec<AssetSymbol, AssetAmount>,
+    /// Trading parameters of instrument resolved from registry at open
+    pub instrument_spec: Option<InstrumentSpec>,
+    /// Commission schedule of order trader group resolved at open
+    pub commission_schedule: Option<CommissionSchedule>,
 }
 
 impl ActivePosition {
@@ -400,26 +612,118 @@ impl ActivePosition {
 
     pub fn set_stop_loss(&mut self, value: Option<StopLossConfig>) {
         self.order.stop_loss = value;
+        self.stop_loss_price = None;
+        self.is_break_even_reached = false;
+        self.update_stop_loss_price();
+        self.update_break_even_stop_loss();
+    }
+
+    pub fn update(&mut self, bidask: &BidAsk, instruments: &InstrumentsRegistry) {
+        self.try_update_instrument_price(bidask, None);
+        self.try_update_asset_price(bidask, instruments);
+        self.update_pnl();
+    }
+
+    /// Sets stop loss price by config. Trailing price is moved only in favor of position
+    pub fn update_stop_loss_price(&mut self) {
+        let Some(stop_loss) = self.order.stop_loss.as_ref() else {
+            self.stop_loss_price = None;
+            return;
+        };
+
+        if !stop_loss.is_trailing() {
+            self.stop_loss_price = match stop_loss.unit {
+                AutoClosePositionUnit::PriceRateUnit => Some(stop_loss.value),
+                _ => None,
+            };
+            return;
+        }
+
+        let Some(trailing_price) = stop_loss.get_trailing_price(self.current_price, &self.order.side) else {
+            return;
+        };
+        let trailing_price = self.round_price(trailing_price);
+
+        let is_moved = match (self.stop_loss_price, &self.order.side) {
+            (None, _) => true,
+            (Some(price), OrderSide::Buy) => trailing_price > price,
+            (Some(price), OrderSide::Sell) => trailing_price < price,
+        };
+
+        if is_moved {
+            self.stop_loss_price = Some(trailing_price);
+        }
+    }
+
+    /// Moves stop loss price to break-even price after break-even profit of config is reached
+    fn update_break_even_stop_loss(&mut self) {
+        let break_even_profit = self
+            .order
+            .stop_loss
+            .as_ref()
+            .and_then(|stop_loss| stop_loss.break_even_profit);
+
+        let Some(break_even_profit) = break_even_profit else {
+            return;
+        };
+
+        if !self.is_break_even_reached {
+            if self.current_pnl < break_even_profit {
+                return;
+            }
+
+            self.is_break_even_reached = true;
+        }
+
+        let break_even_price = match self.break_even_price {
+            Some(price) => price,
+            None => {
+                let Some(price) = self.get_break_even_price() else {
+                    return;
+                };
+                self.break_even_price = Some(price);
+
+                price
+            }
+        };
+
+        let is_moved = match (self.stop_loss_price, &self.order.side) {
+            (None, _) => true,
+            (Some(price), OrderSide::Buy) => break_even_price > price,
+            (Some(price), OrderSide::Sell) => break_even_price < price,
+        };
+
+        if is_moved {
+            self.stop_loss_price = Some(break_even_price);
+        }
     }
 
-    pub fn update(&mut self, bidask: &BidAsk) {
-        self.try_update_instrument_price(bidask);
-        self.try_update_asset_price(bidask);
+    /// Updates position valued by price of instrument valuation mode instead of close price
+    pub fn update_with_valuation(
+        &mut self,
+        bidask: &BidAsk,
+        instruments: &InstrumentsRegistry,
+        valuation_prices: &ValuationPrices,
+    ) {
+        self.try_update_instrument_price(bidask, Some(valuation_prices));
+        self.try_update_asset_price(bidask, instruments);
         self.update_pnl();
     }
 
+    /// Cancels top-ups older than delay by clock time if price moved back by change percent
     pub fn try_cancel_top_ups(
         &mut self,
         price_change_percent: f64,
         delay: Duration,
+        clock: &dyn Clock,
     ) -> Vec<CanceledTopUp> {
         if self.top_ups.is_empty() {
             return Vec::with_capacity(0);
         }
 
         let mut canceled_top_ups = Vec::with_capacity(self.top_ups.len() / 3);
-        let delay_start_date = DateTimeAsMicroseconds::now();
-        let delay_start_date = delay_start_date.sub(delay);
+        let now = clock.now();
+        let delay_start_date = now.sub(delay);
 
         self.top_ups.retain(|top_up| {
             if top_up.date.is_later_than(delay_start_date) {
@@ -464,40 +768,242 @@ impl ActivePosition {
                 }
             }
 
-            canceled_top_ups.push(top_up.to_owned().cancel(self.current_price));
+            canceled_top_ups.push(top_up.to_owned().cancel(self.current_price, clock));
 
             false
         });
 
+        if !canceled_top_ups.is_empty() {
+            self.break_even_price = None;
+        }
+
         canceled_top_ups
     }
 
-    fn try_update_instrument_price(&mut self, bidask: &BidAsk) {
-        if self.order.instrument == bidask.instrument {
-            self.current_price = bidask.get_close_price(&self.order.side)
+    fn try_update_instrument_price(&mut self, bidask: &BidAsk, valuation_prices: Option<&ValuationPrices>) {
+        if self.order.instrument != bidask.instrument {
+            return;
+        }
+
+        self.current_price = bidask.get_close_price(&self.order.side);
+        self.valuation_price = match valuation_prices {
+            Some(valuation_prices) => valuation_prices.get_price(bidask, &self.order.side),
+            None => self.current_price,
+        };
+        self.update_stop_loss_price();
+    }
+
+    /// Updates price by raw quote. Must be called after update with client quote
+    pub fn update_raw_price(&mut self, raw_bidask: &BidAsk) {
+        if self.order.instrument == raw_bidask.instrument {
+            self.current_raw_price = raw_bidask.get_close_price(&self.order.side)
         }
     }
 
-    fn try_update_asset_price(&mut self, bidask: &BidAsk) {
-        for asset in self.total_invest_assets.iter() {
-            let id = BidAsk::get_instrument_symbol(&asset.symbol, &self.order.base_asset);
+    fn try_update_asset_price(&mut self, bidask: &BidAsk, instruments: &InstrumentsRegistry) {
+        let Some(instrument) = instruments.get(&bidask.instrument) else {
+            return;
+        };
 
-            if id == bidask.instrument {
-                let price = bidask.get_asset_price(&asset.symbol, &OrderSide::Sell);
-                let current_asset_price = self.current_asset_prices.get_mut(&asset.symbol);
+        let Some(asset) = instrument.get_counter_asset(&self.order.base_asset) else {
+            return;
+        };
 
-                if let Some(current_asset_price) = current_asset_price {
-                    current_asset_price.price = price;
-                } else {
-                    self.current_asset_prices.insert_or_replace(AssetPrice {price, symbol: asset.symbol.clone()});
-                }
+        if !self.total_invest_assets.contains(asset) {
+            return;
+        }
+
+        // quote without valid price keeps the last asset price
+        let Ok(price) = bidask.try_get_asset_price(instruments, asset, &OrderSide::Sell) else {
+            return;
+        };
+        let current_asset_price = self.current_asset_prices.get_mut(asset);
+
+        if let Some(current_asset_price) = current_asset_price {
+            current_asset_price.price = price;
+        } else {
+            self.current_asset_prices.insert_or_replace(AssetPrice {price, symbol: asset.clone()});
+        }
+    }
+
+    /// Closes fraction of all invested assets. The rest stays in position with recalculated pnl
+    pub fn close_part(
+        &mut self,
+        fraction: f64,
+        reason: ClosePositionReason,
+        pnl_accuracy: Option<u32>,
+        clock: &dyn Clock,
+    ) -> Result<ClosedPosition, TradingError> {
+        if !(fraction > 0.0 && fraction < 1.0) {
+            return Err(TradingError::InvalidAmount(fraction));
+        }
+
+        let part = self.split(|_| fraction);
+
+        Ok(part.close_as_part(self.id.clone(), reason, pnl_accuracy, clock))
+    }
+
+    /// Closes amount of invested asset. The rest stays in position with recalculated pnl
+    pub fn close_asset_amount(
+        &mut self,
+        asset: &AssetSymbol,
+        amount: f64,
+        reason: ClosePositionReason,
+        pnl_accuracy: Option<u32>,
+        clock: &dyn Clock,
+    ) -> Result<ClosedPosition, TradingError> {
+        let mut amounts = SortedVec::new_with_capacity(1);
+        amounts.insert_or_replace(AssetAmount {amount, symbol: asset.clone()});
+
+        self.close_asset_amounts(&amounts, reason, pnl_accuracy, clock)
+    }
+
+    /// Closes amounts of invested assets. Some invested amount must stay in position
+    pub fn close_asset_amounts(
+        &mut self,
+        amounts: &SortedVec<AssetSymbol, AssetAmount>,
+        reason: ClosePositionReason,
+        pnl_accuracy: Option<u32>,
+        clock: &dyn Clock,
+    ) -> Result<ClosedPosition, TradingError> {
+        let mut fractions = Vec::with_capacity(amounts.len());
+
+        for item in amounts.iter() {
+            let Some(invest_amount) = self.total_invest_assets.get(&item.symbol) else {
+                return Err(TradingError::BalanceNotFound(item.symbol.to_string()));
+            };
+
+            if !(item.amount > 0.0 && item.amount <= invest_amount.amount) {
+                return Err(TradingError::InvalidAmount(item.amount));
             }
+
+            fractions.push((item.symbol.clone(), item.amount / invest_amount.amount));
+        }
+
+        let get_fraction = |symbol: &AssetSymbol| {
+            fractions
+                .iter()
+                .find(|(fraction_symbol, _)| fraction_symbol == symbol)
+                .map(|(_, fraction)| *fraction)
+                .unwrap_or(0.0)
+        };
+        let is_all_closed = self
+            .total_invest_assets
+            .iter()
+            .all(|item| get_fraction(&item.symbol) >= 1.0);
+
+        if fractions.is_empty() || is_all_closed {
+            return Err(TradingError::PositionStateMismatch(
+                "Part must keep invested amount in position".to_string(),
+            ));
+        }
+
+        let part = self.split(get_fraction);
+
+        Ok(part.close_as_part(self.id.clone(), reason, pnl_accuracy, clock))
+    }
+
+    /// Moves fractions of invested assets to a new position
+    fn split(&mut self, get_fraction: impl Fn(&AssetSymbol) -> f64) -> ActivePosition {
+        let volume = self.get_volume();
+        let mut part = self.clone();
+        part.id = Position::generate_id();
+        split_amounts(&mut self.order.invest_assets, &mut part.order.invest_assets, &get_fraction);
+        split_amounts(&mut self.total_invest_assets, &mut part.total_invest_assets, &get_fraction);
+        split_amounts(&mut self.bonus_invest_assets, &mut part.bonus_invest_assets, &get_fraction);
+
+        for (top_up, part_top_up) in self.top_ups.iter_mut().zip(part.top_ups.iter_mut()) {
+            split_amounts(&mut top_up.total_assets, &mut part_top_up.total_assets, &get_fraction);
+            split_amounts(&mut top_up.bonus_assets, &mut part_top_up.bonus_assets, &get_fraction);
+        }
+
+        for (increase, part_increase) in self.increases.iter_mut().zip(part.increases.iter_mut()) {
+            split_amounts(&mut increase.invest_assets, &mut part_increase.invest_assets, &get_fraction);
+        }
+
+        for (commission, part_commission) in self.commissions.iter_mut().zip(part.commissions.iter_mut()) {
+            split_amounts(&mut commission.amounts, &mut part_commission.amounts, &get_fraction);
         }
+
+        self.increases.retain(|increase| !increase.invest_assets.is_empty());
+        part.increases.retain(|increase| !increase.invest_assets.is_empty());
+        self.commissions.retain(|commission| !commission.amounts.is_empty());
+        part.commissions.retain(|commission| !commission.amounts.is_empty());
+
+        self.top_ups.retain(|top_up| !top_up.total_assets.is_empty());
+        part.top_ups.retain(|top_up| !top_up.total_assets.is_empty());
+
+        // funding and swaps are split by volume because they are charged in base asset
+        let part_fraction = if volume > 0.0 {
+            part.get_volume() / volume
+        } else {
+            0.0
+        };
+        part.funding_pnl = self.funding_pnl * part_fraction;
+        self.funding_pnl -= part.funding_pnl;
+        part.swap_pnl = self.swap_pnl * part_fraction;
+        self.swap_pnl -= part.swap_pnl;
+        self.break_even_price = None;
+        part.break_even_price = None;
+        self.update_pnl();
+        part.update_pnl();
+
+        part
+    }
+
+    fn close_as_part(
+        self,
+        parent_id: PositionId,
+        reason: ClosePositionReason,
+        pnl_accuracy: Option<u32>,
+        clock: &dyn Clock,
+    ) -> ClosedPosition {
+        let mut position = self.close(reason, pnl_accuracy, None, clock);
+        position.is_partial = true;
+        position.parent_id = Some(parent_id);
+
+        position
     }
 
-    pub fn close(self, reason: ClosePositionReason, pnl_accuracy: Option<u32>) -> ClosedPosition {
+    /// Closes position at clock time. Close price is VWAP price of raw order book
+    /// with markup of client price if order book is set
+    pub fn close(
+        mut self,
+        reason: ClosePositionReason,
+        pnl_accuracy: Option<u32>,
+        order_book: Option<&OrderBook>,
+        clock: &dyn Clock,
+    ) -> ClosedPosition {
+        if let Some(order_book) = order_book {
+            let volume = self.get_instrument_volume();
+            let prices = order_book.get_client_close_price(
+                &self.order.side,
+                volume,
+                self.current_price,
+                self.current_raw_price,
+            );
+
+            if let Some((price, depth_raw_price)) = prices {
+                self.current_price = self.round_price(price);
+                self.current_raw_price = depth_raw_price;
+            }
+        }
+
+        // realized pnl is calculated by execution price
+        self.valuation_price = self.current_price;
+        let close_date = clock.now();
+        let commission = self.calculate_close_commission(close_date);
+        self.commissions.extend(commission);
+        let pnl_accuracy = self
+            .instrument_spec
+            .as_ref()
+            .and_then(|spec| spec.pnl_accuracy)
+            .or(pnl_accuracy);
         let pnls_by_assets = self.calc_pnls_by_assets(pnl_accuracy);
-        let mut total_pnl = calculate_total_amount(&pnls_by_assets, &self.current_asset_prices);
+        let mut total_pnl =
+            calculate_total_amount(&pnls_by_assets, &self.current_asset_prices)
+            + self.funding_pnl
+            + self.swap_pnl;
 
         if let Some(pnl_accuracy) = pnl_accuracy {
             total_pnl = floor(total_pnl, pnl_accuracy);
@@ -513,17 +1019,85 @@ impl ActivePosition {
             activate_date: Some(self.activate_date),
             activate_price: Some(self.activate_price),
             activate_asset_prices: self.activate_asset_prices,
-            close_date: DateTimeAsMicroseconds::now(),
+            close_date,
             close_price: self.current_price,
             close_reason: reason,
             close_asset_prices: self.current_asset_prices.to_owned(),
+            open_raw_price: self.open_raw_price,
+            activate_raw_price: Some(self.activate_raw_price),
+            close_raw_price: self.current_raw_price,
+            stop_loss_price: self.stop_loss_price,
+            is_partial: false,
+            parent_id: None,
             order: self.order,
             id: self.id,
             top_ups: self.top_ups,
+            increases: self.increases,
+            commissions: self.commissions,
+            funding_pnl: self.funding_pnl,
+            swap_pnl: self.swap_pnl,
             invest_bonus_assets: self.bonus_invest_assets,
         }
     }
 
+    /// Closes position at quote and opens opposite side with the same invested assets
+    pub fn reverse(
+        mut self,
+        bidask: &BidAsk,
+        pnl_accuracy: Option<u32>,
+        instruments: &InstrumentsRegistry,
+        clock: &dyn Clock,
+    ) -> Result<(ClosedPosition, ActivePosition), TradingError> {
+        let reversed = self.open_reversed(bidask, bidask, instruments, clock)?;
+        self.try_update_instrument_price(bidask, None);
+        self.update_pnl();
+        let closed = self.close(ClosePositionReason::ClientCommand, pnl_accuracy, None, clock);
+
+        Ok((closed, reversed))
+    }
+
+    /// Opens market position of opposite side with the same invested assets on client quote
+    /// and keeps raw quote prices
+    pub fn open_reversed(
+        &self,
+        bidask: &BidAsk,
+        raw_bidask: &BidAsk,
+        instruments: &InstrumentsRegistry,
+        clock: &dyn Clock,
+    ) -> Result<ActivePosition, TradingError> {
+        let mut order = self.order.clone();
+        order.id = Order::generate_id();
+        order.created_date = clock.now();
+        order.invest_assets = self.total_invest_assets.clone();
+        order.side = match self.order.side {
+            OrderSide::Buy => OrderSide::Sell,
+            OrderSide::Sell => OrderSide::Buy,
+        };
+        order.order_type = OrderType::Market;
+        order.time_in_force = TimeInForce::GoodTillCanceled;
+        order.desire_price = None;
+        order.stop_price = None;
+        order.take_profit = None;
+        order.stop_loss = None;
+        order.reduce_only = false;
+        // reversed position is charged by the schedule resolved at open of this position
+        let mut commissions = CommissionSchedules::new();
+
+        if let Some(schedule) = self.commission_schedule.as_ref() {
+            commissions.add(order.trader_group.as_deref(), schedule.clone());
+        }
+
+        let context = OpenContext::new(instruments, &commissions, clock).with_raw_bidask(raw_bidask);
+        let position = order.try_open(bidask, &self.current_asset_prices, &context)?;
+        let Position::Active(position) = position else {
+            return Err(TradingError::PositionStateMismatch(
+                "Reversed position is not active".to_string(),
+            ));
+        };
+
+        Ok(position)
+    }
+
     pub fn determine_close_reason(&self) -> Option<ClosePositionReason> {
         if self.is_stop_out() {
             return Some(ClosePositionReason::StopOut);
@@ -540,12 +1114,12 @@ impl ActivePosition {
         None
     }
 
-    pub fn try_close(self, pnl_accuracy: Option<u32>) -> Position {
+    pub fn try_close(self, pnl_accuracy: Option<u32>, clock: &dyn Clock) -> Position {
         let Some(reason) = self.determine_close_reason() else {
             return Position::Active(self);
         };
 
-        Position::Closed(self.close(reason, pnl_accuracy))
+        Position::Closed(self.close(reason, pnl_accuracy, None, clock))
     }
 
     fn is_take_profit(&self) -> bool {
@@ -557,6 +1131,19 @@ impl ActivePosition {
     }
 
     fn is_stop_loss(&self) -> bool {
+        let is_trailing = self.order.stop_loss.as_ref().map(|config| config.is_trailing());
+
+        if is_trailing == Some(true) || (is_trailing.is_some() && self.is_break_even_reached) {
+            let Some(stop_loss_price) = self.stop_loss_price else {
+                return false;
+            };
+
+            return match self.order.side {
+                OrderSide::Buy => self.current_price <= stop_loss_price,
+                OrderSide::Sell => self.current_price >= stop_loss_price,
+            };
+        }
+
         if let Some(stop_loss_config) = self.order.stop_loss.as_ref() {
             stop_loss_config.is_triggered(self.current_pnl, self.current_price, &self.order.side)
         } else {
@@ -572,6 +1159,188 @@ impl ActivePosition {
         self.current_loss_percent >= self.order.stop_out_percent
     }
 
+    /// Calculates valuation price at which position is stopped out
+    pub fn get_liquidation_price(&self) -> Option<f64> {
+        self.calculate_loss_price(self.order.stop_out_percent)
+    }
+
+    /// Calculates valuation price of margin call. None if top-up is enabled
+    pub fn get_margin_call_price(&self) -> Option<f64> {
+        if self.order.top_up_enabled {
+            return None;
+        }
+
+        self.calculate_loss_price(self.order.margin_call_percent)
+    }
+
+    /// Calculates valuation price of top-up. None if top-up is disabled
+    pub fn get_top_up_price(&self) -> Option<f64> {
+        if !self.order.top_up_enabled {
+            return None;
+        }
+
+        self.calculate_loss_price(self.order.top_up_percent)
+    }
+
+    /// Calculates valuation price at which loss percent of total invested amount is reached
+    pub fn calculate_loss_price(&self, loss_percent: f64) -> Option<f64> {
+        let invest_amount = try_calculate_total_amount(&self.total_invest_assets, &self.current_asset_prices).ok()?;
+
+        self.calculate_pnl_price(-invest_amount * loss_percent / 100.0)
+    }
+
+    /// Calculates valuation price at which pnl with open and expected close commissions is zero
+    pub fn get_break_even_price(&self) -> Option<f64> {
+        // close commission is charged by close volume, so it is linear by price as pnl
+        let close_commission_factor = match self.calculate_close_commission(self.last_update_date) {
+            Some(commission) if self.valuation_price > 0.0 => {
+                try_calculate_total_amount(&commission.amounts, &self.current_asset_prices).ok()?
+                    / self.valuation_price
+            }
+            _ => 0.0,
+        };
+
+        self.solve_pnl_price(0.0, close_commission_factor)
+    }
+
+    /// Calculates valuation price at which pnl in base asset is reached.
+    /// None if no positive price reaches it
+    pub fn calculate_pnl_price(&self, target_pnl: f64) -> Option<f64> {
+        self.solve_pnl_price(target_pnl, 0.0)
+    }
+
+    /// Solves pnl = target_pnl + price * close_commission_factor for price. Loss of isolated top-up
+    /// is limited by its amounts, so pnl is linear by price between top-up loss limit prices.
+    /// Solution of each price segment is checked against segment bounds and the closest one
+    /// to valuation price is returned
+    fn solve_pnl_price(&self, target_pnl: f64, close_commission_factor: f64) -> Option<f64> {
+        let mut limit_prices = Vec::with_capacity(self.top_ups.len() + 2);
+        limit_prices.push(0.0);
+        limit_prices.extend(self.top_ups.iter().filter_map(|top_up| self.get_top_up_loss_limit_price(top_up)));
+        limit_prices.push(f64::INFINITY);
+        limit_prices.sort_by(|a, b| a.total_cmp(b));
+        let mut result: Option<f64> = None;
+
+        for segment in limit_prices.windows(2) {
+            let (from_price, to_price) = (segment[0], segment[1]);
+
+            if from_price >= to_price {
+                continue;
+            }
+
+            let segment_price = if to_price.is_finite() {
+                (from_price + to_price) / 2.0
+            } else {
+                from_price * 2.0 + 1.0
+            };
+            let Some(price) =
+                self.solve_segment_pnl_price(target_pnl, close_commission_factor, segment_price)
+            else {
+                continue;
+            };
+
+            if price < from_price || price > to_price {
+                continue;
+            }
+
+            let is_closer = result
+                .map(|result| {
+                    (price - self.valuation_price).abs() < (result - self.valuation_price).abs()
+                })
+                .unwrap_or(true);
+
+            if is_closer {
+                result = Some(price);
+            }
+        }
+
+        result
+            .filter(|price| price.is_finite() && *price > 0.0)
+            .map(|price| self.round_price(price))
+    }
+
+    /// Calculates valuation price below which loss of isolated top-up is limited by its amounts
+    fn get_top_up_loss_limit_price(&self, top_up: &ActiveTopUp) -> Option<f64> {
+        let price = match self.order.side {
+            OrderSide::Buy => top_up.instrument_price * (1.0 - 1.0 / self.order.leverage),
+            OrderSide::Sell => top_up.instrument_price * (1.0 + 1.0 / self.order.leverage),
+        };
+
+        if !price.is_finite() || price <= 0.0 {
+            return None;
+        }
+
+        Some(price)
+    }
+
+    /// Solves pnl equation for price with top-up loss limits applied as at segment price.
+    /// None if pnl doesn't depend on price in segment or asset price is missing
+    fn solve_segment_pnl_price(
+        &self,
+        target_pnl: f64,
+        close_commission_factor: f64,
+        segment_price: f64,
+    ) -> Option<f64> {
+        // pnl is linear by price: pnl = price * price_factor + fixed_pnl
+        let mut price_factor = 0.0;
+        let mut fixed_pnl = self.funding_pnl + self.swap_pnl;
+        let volume_ratio = self.get_order_volume_ratio();
+        self.add_pnl_factors(&self.order.invest_assets, self.activate_price, volume_ratio, &mut price_factor, &mut fixed_pnl)?;
+
+        for top_up in self.top_ups.iter() {
+            let is_loss_limited = self
+                .get_top_up_loss_limit_price(top_up)
+                .map(|limit_price| match self.order.side {
+                    OrderSide::Buy => segment_price < limit_price,
+                    OrderSide::Sell => segment_price > limit_price,
+                })
+                .unwrap_or(false);
+
+            if is_loss_limited {
+                fixed_pnl -= try_calculate_total_amount(&top_up.total_assets, &self.current_asset_prices).ok()?;
+            } else {
+                self.add_pnl_factors(&top_up.total_assets, top_up.instrument_price, 1.0, &mut price_factor, &mut fixed_pnl)?;
+            }
+        }
+
+        for increase in self.increases.iter() {
+            let volume_ratio = self.get_increase_volume_ratio(increase);
+            self.add_pnl_factors(&increase.invest_assets, increase.instrument_price, volume_ratio, &mut price_factor, &mut fixed_pnl)?;
+        }
+
+        fixed_pnl -= try_calculate_total_amount(&self.calc_commissions_by_assets(), &self.current_asset_prices).ok()?;
+        price_factor -= close_commission_factor;
+
+        if price_factor == 0.0 {
+            return None;
+        }
+
+        Some((target_pnl - fixed_pnl) / price_factor)
+    }
+
+    fn add_pnl_factors(
+        &self,
+        amounts: &SortedVec<AssetSymbol, AssetAmount>,
+        initial_price: f64,
+        volume_ratio: f64,
+        price_factor: &mut f64,
+        fixed_pnl: &mut f64,
+    ) -> Option<()> {
+        let side_sign = match self.order.side {
+            OrderSide::Buy => 1.0,
+            OrderSide::Sell => -1.0,
+        };
+
+        for item in amounts.iter() {
+            let asset_price = self.current_asset_prices.get(&item.symbol)?.price;
+            let volume = item.amount * volume_ratio * self.order.leverage * asset_price * side_sign;
+            *price_factor += volume / initial_price;
+            *fixed_pnl -= volume;
+        }
+
+        Some(())
+    }
+
     pub fn is_margin_call(&self) -> bool {
         if self.order.top_up_enabled {
             return false;
@@ -599,33 +1368,107 @@ impl ActivePosition {
 
     /// Calculates amount for next top-up in base asset
     pub fn calculate_required_top_up_amount(&self) -> f64 {
+        self.try_calculate_required_top_up_amount()
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Calculates amount for next top-up in base asset
+    pub fn try_calculate_required_top_up_amount(&self) -> Result<f64, TradingError> {
         if !self.is_top_up() {
-            panic!("Position top-up is not possible")
+            return Err(TradingError::PositionStateMismatch(
+                "Position top-up is not possible".to_string(),
+            ));
         }
 
         let total_amount =
-            calculate_total_amount(&self.total_invest_assets, &self.current_asset_prices);
+            try_calculate_total_amount(&self.total_invest_assets, &self.current_asset_prices)?;
 
-        total_amount * self.order.top_up_percent / 100.0
+        Ok(total_amount * self.order.top_up_percent / 100.0)
     }
 
-    /// Calculates total pnl in base asset by position
-    fn calculate_pnl(&self, invest_amount: f64, initial_price: f64) -> f64 {
-        let volume = self.order.calculate_volume(invest_amount);
+    /// Charges funding fees by instrument rate for every funding timestamp passed since the last charge.
+    /// Periods are kept uncharged until instrument rate is set. Returns charged amount in base asset
+    pub fn accrue_funding(&mut self, funding_rates: &FundingRates, now: DateTimeAsMicroseconds) -> f64 {
+        let Some(period) = self.order.funding_fee_period else {
+            return 0.0;
+        };
 
-        match self.order.side {
-            OrderSide::Buy => (self.current_price / initial_price - 1.0) * volume,
-            OrderSide::Sell => (self.current_price / initial_price - 1.0) * -volume,
-        }
-    }
+        let period = period.as_micros() as i64;
 
-    pub fn add_top_up(&mut self, top_up: ActiveTopUp) {
-        for item in top_up.asset_prices.iter() {
-            self.current_asset_prices.insert_or_replace(item.clone());
+        if period <= 0 {
+            return 0.0;
         }
 
-        for item in top_up.total_assets.iter() {
-            let invested_asset_amount = self.total_invest_assets.get_mut(&item.symbol);
+        let periods_count = now.unix_microseconds.div_euclid(period)
+            - self.last_funding_date.unix_microseconds.div_euclid(period);
+
+        if periods_count <= 0 {
+            return 0.0;
+        }
+
+        let Some(rate) = funding_rates.get_rate(&self.order.instrument, &self.order.side) else {
+            return 0.0;
+        };
+
+        self.last_funding_date = now;
+
+        let amount = -self.get_volume() * rate / 100.0 * periods_count as f64;
+        self.funding_pnl += amount;
+        self.break_even_price = None;
+        self.update_pnl();
+
+        amount
+    }
+
+    /// Charges swaps by instrument rate for every rollover passed since the last charge.
+    /// Returns charged amount in base asset
+    pub fn accrue_swaps(&mut self, swap_rates: &SwapRates, now: DateTimeAsMicroseconds) -> f64 {
+        if now.unix_microseconds <= self.last_swap_date.unix_microseconds {
+            return 0.0;
+        }
+
+        let Some(swap_rate) = swap_rates.get(&self.order.instrument) else {
+            return 0.0;
+        };
+
+        let last_swap_date = self.last_swap_date;
+        self.last_swap_date = now;
+
+        let days_count: u32 = swap_rates
+            .get_rollover_dates(last_swap_date, now)
+            .map(|rollover_date| swap_rate.get_multiplier(rollover_date))
+            .sum();
+
+        if days_count == 0 {
+            return 0.0;
+        }
+
+        let rate = swap_rate.get_rate(&self.order.side);
+        let amount = -self.get_volume() * rate / 100.0 * days_count as f64;
+        self.swap_pnl += amount;
+        self.break_even_price = None;
+        self.update_pnl();
+
+        amount
+    }
+
+    /// Calculates total pnl in base asset by position
+    fn calculate_pnl(&self, invest_amount: f64, initial_price: f64, volume_ratio: f64) -> f64 {
+        let volume = invest_amount * volume_ratio * self.order.leverage;
+
+        match self.order.side {
+            OrderSide::Buy => (self.valuation_price / initial_price - 1.0) * volume,
+            OrderSide::Sell => (self.valuation_price / initial_price - 1.0) * -volume,
+        }
+    }
+
+    pub fn add_top_up(&mut self, top_up: ActiveTopUp) {
+        for item in top_up.asset_prices.iter() {
+            self.current_asset_prices.insert_or_replace(item.clone());
+        }
+
+        for item in top_up.total_assets.iter() {
+            let invested_asset_amount = self.total_invest_assets.get_mut(&item.symbol);
 
             if let Some(invested_asset_amount) = invested_asset_amount {
                 invested_asset_amount.amount += item.amount;
@@ -645,12 +1488,17 @@ impl ActivePosition {
         }
 
         self.top_ups.push(top_up);
+        self.break_even_price = None;
         self.update_pnl();
     }
 
-    fn update_pnl(&mut self) {
+    /// Recalculates current pnl and loss percent by current prices, commissions and funding
+    pub fn update_pnl(&mut self) {
         let pnls_by_assets = self.calc_pnls_by_assets(None);
-        self.current_pnl = calculate_total_amount(&pnls_by_assets, &self.current_asset_prices);
+        self.current_pnl =
+            calculate_total_amount(&pnls_by_assets, &self.current_asset_prices)
+            + self.funding_pnl
+            + self.swap_pnl;
         self.prev_loss_percent = self.current_loss_percent;
 
         if self.current_pnl < 0.0 {
@@ -661,6 +1509,8 @@ impl ActivePosition {
         } else {
             self.current_loss_percent = 0.0;
         }
+
+        self.update_break_even_stop_loss();
     }
 
     /// Calculates total asset amounts invested to position. Including order and all active top-ups
@@ -736,15 +1586,290 @@ impl ActivePosition {
             }
         }
 
+        for item in self.calc_increases_pnls_by_assets().iter() {
+            let asset_pnl: Option<&mut AssetAmount> = asset_pnls.get_mut(&item.symbol);
+
+            if let Some(asset_pnl) = asset_pnl {
+                asset_pnl.amount += item.amount;
+
+                if let Some(pnl_accuracy) = pnl_accuracy {
+                    asset_pnl.amount = floor(asset_pnl.amount, pnl_accuracy);
+                };
+            } else {
+                let amount = if let Some(pnl_accuracy) = pnl_accuracy {
+                    floor(item.amount, pnl_accuracy)
+                } else {
+                    item.amount
+                };
+
+                asset_pnls.insert_or_replace(assets::AssetAmount {symbol: item.symbol.clone(), amount});
+            }
+        }
+
+        for item in self.calc_commissions_by_assets().iter() {
+            let asset_pnl: Option<&mut AssetAmount> = asset_pnls.get_mut(&item.symbol);
+
+            if let Some(asset_pnl) = asset_pnl {
+                asset_pnl.amount -= item.amount;
+
+                if let Some(pnl_accuracy) = pnl_accuracy {
+                    asset_pnl.amount = floor(asset_pnl.amount, pnl_accuracy);
+                };
+            } else {
+                let amount = if let Some(pnl_accuracy) = pnl_accuracy {
+                    floor(-item.amount, pnl_accuracy)
+                } else {
+                    -item.amount
+                };
+
+                asset_pnls.insert_or_replace(assets::AssetAmount {symbol: item.symbol.clone(), amount});
+            }
+        }
+
         asset_pnls
     }
 
+    /// Calculates open commission of order invested amounts at activation
+    pub fn calculate_order_commission(&self) -> Option<PositionCommission> {
+        let volume_ratio = self.get_order_volume_ratio();
+
+        self.calculate_commission(CommissionType::Open, &self.order.invest_assets, volume_ratio, self.activate_date)
+    }
+
+    /// Calculates close commission by volume of order and increases at valuation price.
+    /// Top-ups add margin only, so they aren't charged
+    pub fn calculate_close_commission(&self, date: DateTimeAsMicroseconds) -> Option<PositionCommission> {
+        let volume_factor = self.get_order_volume_ratio() * self.valuation_price / self.activate_price;
+        let mut commission =
+            self.calculate_commission(CommissionType::Close, &self.order.invest_assets, volume_factor, date)?;
+
+        for increase in self.increases.iter() {
+            let volume_factor =
+                self.get_increase_volume_ratio(increase) * self.valuation_price / increase.instrument_price;
+            let increase_commission =
+                self.calculate_commission(CommissionType::Close, &increase.invest_assets, volume_factor, date)?;
+            add_amounts(&mut commission.amounts, &increase_commission.amounts);
+        }
+
+        Some(commission)
+    }
+
+    /// Calculates commission by position schedule for invested amounts with volume multiplied by factor
+    fn calculate_commission(
+        &self,
+        commission_type: CommissionType,
+        invest_assets: &SortedVec<AssetSymbol, AssetAmount>,
+        volume_factor: f64,
+        date: DateTimeAsMicroseconds,
+    ) -> Option<PositionCommission> {
+        let schedule = self.commission_schedule.as_ref()?;
+
+        Some(PositionCommission {
+            commission_type,
+            date,
+            amounts: schedule.calculate(invest_assets, self.order.leverage * volume_factor),
+        })
+    }
+
+    /// Calculates total commissions by invested assets
+    pub fn calc_commissions_by_assets(&self) -> SortedVec<AssetSymbol, AssetAmount> {
+        let mut amounts: SortedVec<AssetSymbol, AssetAmount> = SortedVec::new_with_capacity(self.total_invest_assets.len());
+
+        for commission in self.commissions.iter() {
+            for item in commission.amounts.iter() {
+                let amount = amounts.get_mut(&item.symbol);
+
+                if let Some(amount) = amount {
+                    amount.amount += item.amount;
+                } else {
+                    amounts.insert_or_replace(item.clone());
+                }
+            }
+        }
+
+        amounts
+    }
+
+    /// Calculates pnl by invested assets in increases
+    pub fn calc_increases_pnls_by_assets(&self) -> SortedVec<AssetSymbol, AssetAmount> {
+        let mut pnls_by_assets = SortedVec::new_with_capacity(self.order.invest_assets.len());
+
+        for increase in self.increases.iter() {
+            let volume_ratio = self.get_increase_volume_ratio(increase);
+
+            for item in increase.invest_assets.iter() {
+                let pnl = self.calculate_pnl(item.amount, increase.instrument_price, volume_ratio);
+                let total_asset_pnl: Option<&mut AssetAmount> = pnls_by_assets.get_mut(&item.symbol);
+
+                if let Some(total_asset_pnl) = total_asset_pnl {
+                    total_asset_pnl.amount += pnl;
+                } else {
+                    pnls_by_assets.insert_or_replace(assets::AssetAmount {amount: pnl, symbol: item.symbol.clone()});
+                }
+            }
+        }
+
+        pnls_by_assets
+    }
+
+    /// Adds invest assets to position volume at open price of quote
+    pub fn increase(
+        &mut self,
+        bidask: &BidAsk,
+        invest_assets: &SortedVec<AssetSymbol, AssetAmount>,
+        clock: &dyn Clock,
+    ) -> Result<PositionIncrease, TradingError> {
+        if bidask.instrument != self.order.instrument {
+            return Err(TradingError::InvalidInstrument(format!(
+                "BidAsk instrument {} must be {}",
+                bidask.instrument, self.order.instrument
+            )));
+        }
+
+        if invest_assets.is_empty() {
+            return Err(TradingError::InvalidAmount(0.0));
+        }
+
+        for item in invest_assets.iter() {
+            if !item.amount.is_finite() || item.amount <= 0.0 {
+                return Err(TradingError::InvalidAmount(item.amount));
+            }
+
+            if !self.current_asset_prices.contains(&item.symbol) {
+                return Err(TradingError::MissingPrice(item.symbol.to_string()));
+            }
+        }
+
+        for item in invest_assets.iter() {
+            let invested_asset_amount = self.total_invest_assets.get_mut(&item.symbol);
+
+            if let Some(invested_asset_amount) = invested_asset_amount {
+                invested_asset_amount.amount += item.amount;
+            } else {
+                self.total_invest_assets.insert_or_replace(item.clone());
+            }
+        }
+
+        let increase = PositionIncrease {
+            id: Uuid::new_v4().to_string(),
+            date: clock.now(),
+            invest_assets: invest_assets.to_owned(),
+            instrument_price: bidask.get_open_price(&self.order.side),
+            asset_prices: self.current_asset_prices.to_owned(),
+        };
+        let volume_ratio = self.get_increase_volume_ratio(&increase);
+        let commission = self.calculate_commission(CommissionType::Open, invest_assets, volume_ratio, increase.date);
+        self.increases.push(increase.clone());
+        self.commissions.extend(commission);
+        self.break_even_price = None;
+        self.update_pnl();
+
+        Ok(increase)
+    }
+
+    /// Calculates volume in base asset of order and increases at their asset prices
+    pub fn get_volume(&self) -> f64 {
+        let mut volume = self.calculate_volume(
+            calculate_total_amount(&self.order.invest_assets, &self.activate_asset_prices),
+            self.activate_price,
+        );
+
+        for increase in self.increases.iter() {
+            volume += self.calculate_volume(
+                calculate_total_amount(&increase.invest_assets, &increase.asset_prices),
+                increase.instrument_price,
+            );
+        }
+
+        volume
+    }
+
+    /// Calculates volume in instrument base asset of order and increases at their prices
+    pub fn get_instrument_volume(&self) -> f64 {
+        let mut volume = self.calculate_volume(
+            calculate_total_amount(&self.order.invest_assets, &self.activate_asset_prices),
+            self.activate_price,
+        ) / self.activate_price;
+
+        for increase in self.increases.iter() {
+            volume += self.calculate_volume(
+                calculate_total_amount(&increase.invest_assets, &increase.asset_prices),
+                increase.instrument_price,
+            ) / increase.instrument_price;
+        }
+
+        volume
+    }
+
+    /// Calculates volume in base asset of invested amount. Instrument volume at price
+    /// is floored to lot step of instrument spec
+    pub fn calculate_volume(&self, invest_amount: f64, instrument_price: f64) -> f64 {
+        let volume = self.order.calculate_volume(invest_amount);
+
+        match self.instrument_spec.as_ref() {
+            Some(spec) if instrument_price > 0.0 => {
+                spec.floor_volume(volume / instrument_price) * instrument_price
+            }
+            _ => volume,
+        }
+    }
+
+    /// Returns part of order volume left after flooring to lot step
+    fn get_order_volume_ratio(&self) -> f64 {
+        self.get_volume_ratio(&self.order.invest_assets, &self.activate_asset_prices, self.activate_price)
+    }
+
+    fn get_increase_volume_ratio(&self, increase: &PositionIncrease) -> f64 {
+        self.get_volume_ratio(&increase.invest_assets, &increase.asset_prices, increase.instrument_price)
+    }
+
+    fn get_volume_ratio(
+        &self,
+        invest_assets: &SortedVec<AssetSymbol, AssetAmount>,
+        asset_prices: &SortedVec<AssetSymbol, AssetPrice>,
+        instrument_price: f64,
+    ) -> f64 {
+        if self.instrument_spec.is_none() {
+            return 1.0;
+        }
+
+        let invest_amount = calculate_total_amount(invest_assets, asset_prices);
+        let volume = self.order.calculate_volume(invest_amount);
+
+        if volume <= 0.0 {
+            return 1.0;
+        }
+
+        self.calculate_volume(invest_amount, instrument_price) / volume
+    }
+
+    /// Rounds price to tick size of instrument spec
+    pub fn round_price(&self, price: f64) -> f64 {
+        match self.instrument_spec.as_ref() {
+            Some(spec) => spec.round_price(price),
+            None => price,
+        }
+    }
+
+    /// Calculates entry price of order and increases weighted by their volumes
+    pub fn get_average_price(&self) -> f64 {
+        let instrument_volume = self.get_instrument_volume();
+
+        if instrument_volume == 0.0 {
+            return self.activate_price;
+        }
+
+        self.get_volume() / instrument_volume
+    }
+
     /// Calculates pnl by invested assets initially in order
     pub fn calc_order_pnls_by_assets(&self) -> SortedVec<AssetSymbol, AssetAmount> {
         let mut pnls_by_assets = SortedVec::new_with_capacity(self.order.invest_assets.len());
 
+        let volume_ratio = self.get_order_volume_ratio();
+
         for item in self.order.invest_assets.iter() {
-            let pnl = self.calculate_pnl(item.amount, self.activate_price);
+            let pnl = self.calculate_pnl(item.amount, self.activate_price, volume_ratio);
 
             pnls_by_assets.insert_or_replace(assets::AssetAmount { amount:pnl, symbol: item.symbol.clone()});
         }
@@ -758,7 +1883,7 @@ impl ActivePosition {
 
         for top_up in self.top_ups.iter() {
             for item in top_up.total_assets.iter() {
-                let pnl = self.calculate_pnl(item.amount, top_up.instrument_price);
+                let pnl = self.calculate_pnl(item.amount, top_up.instrument_price, 1.0);
                 let max_loss_amount = item.amount * -1.0; // limit for isolated trade
                 let pnl = if pnl < max_loss_amount {
                     max_loss_amount
@@ -780,6 +1905,52 @@ impl ActivePosition {
     }
 }
 
+/// Adds other amounts to amounts of the same assets
+fn add_amounts(
+    amounts: &mut SortedVec<AssetSymbol, AssetAmount>,
+    other_amounts: &SortedVec<AssetSymbol, AssetAmount>,
+) {
+    for item in other_amounts.iter() {
+        let amount = amounts.get_mut(&item.symbol);
+
+        if let Some(amount) = amount {
+            amount.amount += item.amount;
+        } else {
+            amounts.insert_or_replace(item.clone());
+        }
+    }
+}
+
+/// Multiplies part amounts by fractions and keeps the rest in amounts. Zero amounts are removed
+fn split_amounts(
+    amounts: &mut SortedVec<AssetSymbol, AssetAmount>,
+    part_amounts: &mut SortedVec<AssetSymbol, AssetAmount>,
+    get_fraction: &impl Fn(&AssetSymbol) -> f64,
+) {
+    for item in part_amounts.iter_mut() {
+        item.amount *= get_fraction(&item.symbol);
+    }
+
+    for item in amounts.iter_mut() {
+        item.amount *= 1.0 - get_fraction(&item.symbol);
+    }
+
+    remove_zero_amounts(amounts);
+    remove_zero_amounts(part_amounts);
+}
+
+fn remove_zero_amounts(amounts: &mut SortedVec<AssetSymbol, AssetAmount>) {
+    let zero_symbols: Vec<AssetSymbol> = amounts
+        .iter()
+        .filter(|item| item.amount <= 0.0)
+        .map(|item| item.symbol.clone())
+        .collect();
+
+    for symbol in zero_symbols.iter() {
+        amounts.remove(symbol);
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ClosedPosition {
     pub id: PositionId,
@@ -794,9 +1965,28 @@ pub struct ClosedPosition {
     pub close_date: DateTimeAsMicroseconds,
     pub close_reason: ClosePositionReason,
     pub close_asset_prices: SortedVec<AssetSymbol, AssetPrice>,
+    /// Open price by raw quote before markup
+    pub open_raw_price: f64,
+    /// Activate price by raw quote before markup
+    pub activate_raw_price: Option<f64>,
+    /// Close price by raw quote before markup
+    pub close_raw_price: f64,
+    /// Stop loss trigger price at close
+    pub stop_loss_price: Option<f64>,
+    /// Part of position was closed and the rest stays active
+    pub is_partial: bool,
+    /// Id of active position the closed part was split from
+    pub parent_id: Option<PositionId>,
     pub pnl: Option<f64>,
     pub asset_pnls: SortedVec<AssetSymbol, AssetAmount>,
+    /// Funding fees in base asset. Included in pnl
+    pub funding_pnl: f64,
+    /// Overnight swaps in base asset. Included in pnl
+    pub swap_pnl: f64,
     pub top_ups: Vec<ActiveTopUp>,
+    pub increases: Vec<PositionIncrease>,
+    /// Commissions charged on open, increases and close. Deducted from asset pnls
+    pub commissions: Vec<PositionCommission>,
     pub total_invest_assets: SortedVec<AssetSymbol, AssetAmount>,
     pub invest_bonus_assets: SortedVec<AssetSymbol, AssetAmount>,
 }
@@ -814,13 +2004,22 @@ impl ClosedPosition {
 #[cfg(test)]
 mod tests {
     use super::{ActivePosition, ClosePositionReason};
-    use crate::{assets, orders::{Order, OrderSide, TakeProfitConfig}, positions::{BidAsk, Position}};
+    use crate::{assets, orders::{AutoClosePositionUnit, OpenContext, Order, OrderSide, OrderType, OrderViolation, StopLossConfig, TakeProfitConfig, TimeInForce}, positions::{BidAsk, Position}};
     use rust_extensions::date_time::DateTimeAsMicroseconds;
     use rust_extensions::sorted_vec::SortedVec;
+    use std::time::Duration;
     use uuid::Uuid;
     use crate::asset_symbol::AssetSymbol;
     use crate::assets::{AssetAmount, AssetPrice};
+    use crate::clock::{ManualClock, SystemClock};
+    use crate::commissions::{CommissionSchedule, CommissionSchedules, CommissionType};
+    use crate::errors::TradingError;
+    use crate::fundings::{FundingRate, FundingRates};
     use crate::instrument_symbol::InstrumentSymbol;
+    use crate::instruments::{Instrument, InstrumentSpec, InstrumentsRegistry};
+    use crate::markups::{MarkupProfile, MarkupProfiles};
+    use crate::swaps::{SwapRate, SwapRates, Weekday};
+    use crate::order_books::{OrderBook, OrderBookLevel};
     use crate::top_ups::ActiveTopUp;
 
     #[tokio::test]
@@ -834,7 +2033,10 @@ mod tests {
             trader_id: "test".to_string(),
             wallet_id: Uuid::new_v4().into(),
             created_date: DateTimeAsMicroseconds::now(),
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::GoodTillCanceled,
             desire_price: None,
+            stop_price: None,
             funding_fee_period: None,
             invest_assets,
             leverage: 1.0,
@@ -845,6 +2047,8 @@ mod tests {
             margin_call_percent: 10.0,
             top_up_enabled: false,
             top_up_percent: 10.0,
+            trader_group: None,
+            reduce_only: false,
         };
         let mut prices = SortedVec::new();
         prices.insert_or_replace(assets::AssetPrice{ price: 22300.0, symbol: "BTC".into()});
@@ -854,7 +2058,7 @@ mod tests {
             datetime: DateTimeAsMicroseconds::now(),
             instrument: "ATOMUSDT".into(),
         };
-        let position = order.open(&bidask, &prices);
+        let position = order.open(&bidask, &prices, &OpenContext::new(&new_instruments(), &CommissionSchedules::new(), &SystemClock));
         let mut position = match position {
             Position::Active(position) => position,
             _ => {
@@ -863,7 +2067,7 @@ mod tests {
         };
 
         position.current_price = 14.75;
-        let closed_position = position.close(ClosePositionReason::ClientCommand, None);
+        let closed_position = position.close(ClosePositionReason::ClientCommand, None, None, &SystemClock);
 
         let pnl = closed_position.pnl.unwrap();
         let asset_pnl = closed_position.asset_pnls.get(&AssetSymbol("BTC".into())).clone().unwrap();
@@ -896,13 +2100,369 @@ mod tests {
         position.set_take_profit(Some(take_profit));
         position.current_price = 13.817;
 
-        let position = position.try_close(None);
+        let position = position.try_close(None, &SystemClock);
         let _position = match position {
             Position::Closed(position) => position,
             _ => panic!("must be closed"),
         };
     }
 
+    #[test]
+    fn trailing_stop_loss_follows_close_price() {
+        let instruments = new_instruments();
+        let mut prices = SortedVec::new();
+        prices.insert_or_replace(AssetPrice {price: 1.0, symbol: "USDT".into()});
+        let mut invest_assets = SortedVec::new();
+        invest_assets.insert_or_replace(assets::AssetAmount {amount: 100.0, symbol: "USDT".into()});
+        let order = new_order("ATOMUSDT".into(), invest_assets, 1.0, OrderSide::Buy);
+        let mut position = new_active_position(order, &BidAsk::new_synthetic("ATOMUSDT".into(), 10.0, 10.0), &prices);
+        position.set_stop_loss(Some(StopLossConfig {
+            unit: AutoClosePositionUnit::TrailingPriceUnit,
+            value: 1.0,
+            break_even_profit: None,
+        }));
+
+        position.update(&BidAsk::new_synthetic("ATOMUSDT".into(), 12.0, 12.0), &instruments);
+        position.update(&BidAsk::new_synthetic("ATOMUSDT".into(), 11.5, 11.5), &instruments);
+
+        assert_eq!(position.stop_loss_price, Some(11.0));
+        assert!(position.determine_close_reason().is_none());
+
+        position.update(&BidAsk::new_synthetic("ATOMUSDT".into(), 10.9, 10.9), &instruments);
+        let Position::Closed(position) = position.try_close(None, &SystemClock) else {
+            panic!("must be closed");
+        };
+
+        assert!(matches!(position.close_reason, ClosePositionReason::StopLoss));
+        assert_eq!(position.stop_loss_price, Some(11.0));
+    }
+
+    #[test]
+    fn close_part_of_position() {
+        let instruments = new_instruments();
+        let mut prices = SortedVec::new();
+        prices.insert_or_replace(AssetPrice {price: 1.0, symbol: "USDT".into()});
+        let mut invest_assets = SortedVec::new();
+        invest_assets.insert_or_replace(assets::AssetAmount {amount: 100.0, symbol: "USDT".into()});
+        let order = new_order("ATOMUSDT".into(), invest_assets, 2.0, OrderSide::Buy);
+        let mut position = new_active_position(order, &BidAsk::new_synthetic("ATOMUSDT".into(), 10.0, 10.0), &prices);
+        position.update(&BidAsk::new_synthetic("ATOMUSDT".into(), 11.0, 11.0), &instruments);
+
+        let closed_position = position
+            .close_part(0.25, ClosePositionReason::ClientCommand, None, &SystemClock)
+            .unwrap();
+
+        let asset_pnl = closed_position.asset_pnls.get(&"USDT".into()).unwrap();
+        assert!(closed_position.is_partial);
+        assert_ne!(closed_position.id, position.id);
+        assert_eq!(closed_position.parent_id.as_ref(), Some(&position.id));
+        assert_eq!(closed_position.total_invest_assets.get(&"USDT".into()).unwrap().amount, 25.0);
+        assert!((asset_pnl.amount - 5.0).abs() < 1e-9);
+        assert_eq!(position.total_invest_assets.get(&"USDT".into()).unwrap().amount, 75.0);
+        assert!((position.current_pnl - 15.0).abs() < 1e-9);
+        assert!(position.close_part(1.0, ClosePositionReason::ClientCommand, None, &SystemClock).is_err());
+    }
+
+    #[test]
+    fn increase_position_averages_price() {
+        let instruments = new_instruments();
+        let mut prices = SortedVec::new();
+        prices.insert_or_replace(AssetPrice {price: 1.0, symbol: "USDT".into()});
+        let mut invest_assets = SortedVec::new();
+        invest_assets.insert_or_replace(assets::AssetAmount {amount: 100.0, symbol: "USDT".into()});
+        let order = new_order("ATOMUSDT".into(), invest_assets.clone(), 2.0, OrderSide::Buy);
+        let mut position = new_active_position(order, &BidAsk::new_synthetic("ATOMUSDT".into(), 10.0, 10.0), &prices);
+        let bidask = BidAsk::new_synthetic("ATOMUSDT".into(), 12.0, 12.0);
+        position.update(&bidask, &instruments);
+
+        let increase = position.increase(&bidask, &invest_assets, &SystemClock).unwrap();
+
+        assert_eq!(increase.instrument_price, 12.0);
+        assert_eq!(position.increases.len(), 1);
+        assert_eq!(position.total_invest_assets.get(&"USDT".into()).unwrap().amount, 200.0);
+        assert_eq!(position.get_volume(), 400.0);
+        assert!((position.get_average_price() - 400.0 / (20.0 + 200.0 / 12.0)).abs() < 1e-9);
+        assert!((position.current_pnl - 40.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn open_with_instrument_spec() {
+        let mut prices = SortedVec::new();
+        prices.insert_or_replace(AssetPrice {price: 1.0, symbol: "USDT".into()});
+        let mut invest_assets = SortedVec::new();
+        invest_assets.insert_or_replace(assets::AssetAmount {amount: 101.0, symbol: "USDT".into()});
+        let mut order = new_order("ATOMUSDT".into(), invest_assets, 2.0, OrderSide::Buy);
+        order.stop_loss = Some(StopLossConfig {value: 9.123, unit: AutoClosePositionUnit::PriceRateUnit, break_even_profit: None});
+        let instruments = InstrumentsRegistry::new(vec![
+            Instrument::new("ATOMUSDT", "ATOM", "USDT").with_spec(InstrumentSpec {
+                tick_size: 0.01,
+                lot_step: 1.0,
+                min_volume: 10.0,
+                max_volume: Some(1000.0),
+                max_leverage: Some(10.0),
+                pnl_accuracy: Some(2),
+            }),
+        ]);
+        let bidask = BidAsk::new_synthetic("ATOMUSDT".into(), 10.0, 10.0);
+
+        let mut large_order = order.clone();
+        large_order.leverage = 15.0;
+        assert_eq!(
+            large_order.try_open(&bidask, &prices, &OpenContext::new(&instruments, &CommissionSchedules::new(), &SystemClock)).unwrap_err(),
+            TradingError::InvalidLeverage(15.0)
+        );
+
+        let mut small_order = order.clone();
+        small_order.invest_assets.insert_or_replace(assets::AssetAmount {amount: 40.0, symbol: "USDT".into()});
+        assert!(matches!(
+            small_order.try_open(&bidask, &prices, &OpenContext::new(&instruments, &CommissionSchedules::new(), &SystemClock)),
+            Err(TradingError::InvalidOrder(_))
+        ));
+
+        let Position::Active(mut position) = order.open(&bidask, &prices, &OpenContext::new(&instruments, &CommissionSchedules::new(), &SystemClock)) else {
+            panic!("Must be active position");
+        };
+        assert_eq!(position.order.stop_loss.as_ref().unwrap().value, 9.12);
+        assert_eq!(position.get_volume(), 200.0);
+        assert!(position.instrument_spec.is_some());
+
+        position.update(&BidAsk::new_synthetic("ATOMUSDT".into(), 10.5, 10.5), &instruments);
+        assert!((position.current_pnl - 10.0).abs() < 1e-9);
+
+        position.current_price = 10.00123;
+        let closed = position.close(ClosePositionReason::ClientCommand, None, None, &SystemClock);
+        assert_eq!(closed.pnl, Some(0.02));
+    }
+
+    #[test]
+    fn accrue_funding_every_period() {
+        let mut prices = SortedVec::new();
+        prices.insert_or_replace(AssetPrice {price: 1.0, symbol: "USDT".into()});
+        let mut invest_assets = SortedVec::new();
+        invest_assets.insert_or_replace(assets::AssetAmount {amount: 100.0, symbol: "USDT".into()});
+        let mut order = new_order("ATOMUSDT".into(), invest_assets, 2.0, OrderSide::Sell);
+        order.funding_fee_period = Some(Duration::from_secs(8 * 60 * 60));
+        let mut position = new_active_position(order, &BidAsk::new_synthetic("ATOMUSDT".into(), 10.0, 10.0), &prices);
+        // 2024-01-01 01:00 UTC
+        position.last_funding_date = DateTimeAsMicroseconds::new(1_704_070_800_000_000);
+        let mut funding_rates = FundingRates::new();
+        let now = position.last_funding_date.add(Duration::from_secs(17 * 60 * 60));
+
+        assert_eq!(position.accrue_funding(&funding_rates, now), 0.0);
+        assert_eq!(position.last_funding_date.unix_microseconds, 1_704_070_800_000_000);
+
+        funding_rates.update(FundingRate {instrument: "ATOMUSDT".into(), buy_rate: 0.01, sell_rate: -0.01});
+        let amount = position.accrue_funding(&funding_rates, now);
+
+        // charged at 08:00 and 16:00
+        assert!((amount - 0.04).abs() < 1e-9);
+        assert!((position.current_pnl - 0.04).abs() < 1e-9);
+        assert_eq!(position.accrue_funding(&funding_rates, now), 0.0);
+        assert_eq!(position.accrue_funding(&funding_rates, now.add(Duration::from_secs(5 * 60 * 60))), 0.0);
+        assert!((position.accrue_funding(&funding_rates, now.add(Duration::from_secs(7 * 60 * 60))) - 0.02).abs() < 1e-9);
+        let closed = position.close(ClosePositionReason::ClientCommand, None, None, &SystemClock);
+        assert!((closed.funding_pnl - 0.06).abs() < 1e-9);
+        assert!((closed.pnl.unwrap() - 0.06).abs() < 1e-9);
+    }
+
+    #[test]
+    fn charge_open_and_close_commissions() {
+        let mut prices = SortedVec::new();
+        prices.insert_or_replace(AssetPrice {price: 1.0, symbol: "USDT".into()});
+        let mut invest_assets = SortedVec::new();
+        invest_assets.insert_or_replace(assets::AssetAmount {amount: 100.0, symbol: "USDT".into()});
+        let order = new_order("ATOMUSDT".into(), invest_assets, 2.0, OrderSide::Buy);
+        let mut schedules = CommissionSchedules::new();
+        schedules.add(None, CommissionSchedule {
+            instrument: "ATOMUSDT".into(),
+            volume_percent: 0.1,
+            per_lot: 0.0,
+            lot_volume: 0.0,
+        });
+
+        let Position::Active(mut position) = order.open(&BidAsk::new_synthetic("ATOMUSDT".into(), 10.0, 10.0), &prices, &OpenContext::new(&new_instruments(), &schedules, &SystemClock)) else {
+            panic!("Must be active position");
+        };
+        assert!((position.current_pnl + 0.2).abs() < 1e-9);
+        // 20 * (price - 10) - 0.2 open commission - 0.02 * price close commission is zero
+        assert!((position.get_break_even_price().unwrap() - 200.2 / 19.98).abs() < 1e-9);
+
+        position.update(&BidAsk::new_synthetic("ATOMUSDT".into(), 12.0, 12.0), &new_instruments());
+        let mut top_up_assets = SortedVec::new();
+        top_up_assets.insert_or_replace(AssetAmount {amount: 50.0, symbol: "USDT".into()});
+        position.add_top_up(ActiveTopUp {
+            id: "1".to_string(),
+            date: DateTimeAsMicroseconds::now(),
+            total_assets: top_up_assets,
+            instrument_price: 12.0,
+            asset_prices: prices.clone(),
+            bonus_assets: SortedVec::new(),
+        });
+        let closed = position.close(ClosePositionReason::ClientCommand, None, None, &SystemClock);
+
+        // close volume is 200 of entry volume at 12 / 10 of price, top-up isn't charged
+        assert_eq!(closed.commissions.len(), 2);
+        assert_eq!(closed.commissions[1].commission_type, CommissionType::Close);
+        assert!((closed.commissions[1].amounts.get(&"USDT".into()).unwrap().amount - 0.24).abs() < 1e-9);
+        assert!((closed.pnl.unwrap() - 39.56).abs() < 1e-9);
+        assert!((closed.asset_pnls.get(&"USDT".into()).unwrap().amount - 39.56).abs() < 1e-9);
+    }
+
+    #[test]
+    fn accrue_swaps_with_triple_swap_day() {
+        let mut prices = SortedVec::new();
+        prices.insert_or_replace(AssetPrice {price: 1.0, symbol: "USDT".into()});
+        let mut invest_assets = SortedVec::new();
+        invest_assets.insert_or_replace(assets::AssetAmount {amount: 100.0, symbol: "USDT".into()});
+        let order = new_order("ATOMUSDT".into(), invest_assets, 2.0, OrderSide::Buy);
+        let mut position = new_active_position(order, &BidAsk::new_synthetic("ATOMUSDT".into(), 10.0, 10.0), &prices);
+        let mut swap_rates = SwapRates::new(Duration::from_secs(22 * 60 * 60));
+        // 2024-01-01 10:00 UTC is Monday
+        position.last_swap_date = DateTimeAsMicroseconds::new(1_704_103_200_000_000);
+        let now = position.last_swap_date.add(Duration::from_secs(62 * 60 * 60));
+
+        assert_eq!(position.accrue_swaps(&swap_rates, now), 0.0);
+        assert_eq!(position.last_swap_date.unix_microseconds, 1_704_103_200_000_000);
+
+        swap_rates.update(SwapRate {
+            instrument: "ATOMUSDT".into(),
+            long_rate: 0.01,
+            short_rate: -0.01,
+            triple_swap_day: Weekday::Wednesday,
+        });
+        let amount = position.accrue_swaps(&swap_rates, now);
+
+        // Monday and Tuesday rollovers and triple Wednesday rollover
+        assert!((amount + 0.1).abs() < 1e-9);
+        assert!((position.current_pnl + 0.1).abs() < 1e-9);
+        let closed = position.close(ClosePositionReason::ClientCommand, None, None, &SystemClock);
+        assert!((closed.swap_pnl + 0.1).abs() < 1e-9);
+        assert!((closed.pnl.unwrap() + 0.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn calculate_liquidation_price() {
+        let instruments = new_instruments();
+        let mut prices = SortedVec::new();
+        prices.insert_or_replace(AssetPrice {price: 1.0, symbol: "USDT".into()});
+        let mut invest_assets = SortedVec::new();
+        invest_assets.insert_or_replace(assets::AssetAmount {amount: 100.0, symbol: "USDT".into()});
+        let order = new_order("ATOMUSDT".into(), invest_assets, 10.0, OrderSide::Buy);
+        let bidask = BidAsk::new_synthetic("ATOMUSDT".into(), 10.0, 10.0);
+
+        assert!((order.preview_liquidation_price(&bidask, &prices, &instruments, &CommissionSchedules::new()).unwrap().unwrap() - 9.1).abs() < 1e-9);
+
+        let mut position = new_active_position(order, &bidask, &prices);
+        let liquidation_price = position.get_liquidation_price().unwrap();
+
+        assert!((liquidation_price - 9.1).abs() < 1e-9);
+        assert!((position.get_margin_call_price().unwrap() - 9.3).abs() < 1e-9);
+        assert!(position.get_top_up_price().is_none());
+
+        position.update(&BidAsk::new_synthetic("ATOMUSDT".into(), liquidation_price, liquidation_price), &instruments);
+        assert!((position.current_loss_percent - 90.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn calculate_liquidation_price_with_top_up_loss_limit() {
+        let instruments = new_instruments();
+        let mut prices = SortedVec::new();
+        prices.insert_or_replace(AssetPrice {price: 1.0, symbol: "USDT".into()});
+        let mut invest_assets = SortedVec::new();
+        invest_assets.insert_or_replace(assets::AssetAmount {amount: 100.0, symbol: "USDT".into()});
+        let order = new_order("ATOMUSDT".into(), invest_assets, 5.0, OrderSide::Buy);
+        let mut position = new_active_position(order, &BidAsk::new_synthetic("ATOMUSDT".into(), 10.0, 10.0), &prices);
+        let mut top_up_assets = SortedVec::new();
+        top_up_assets.insert_or_replace(AssetAmount {amount: 100.0, symbol: "USDT".into()});
+        position.add_top_up(ActiveTopUp {
+            id: "1".to_string(),
+            date: DateTimeAsMicroseconds::now(),
+            total_assets: top_up_assets,
+            instrument_price: 12.0,
+            asset_prices: prices,
+            bonus_assets: SortedVec::new(),
+        });
+
+        // top-up loss is limited by 100 below 9.6, so 500 * (price / 10 - 1) - 100 is -180
+        let liquidation_price = position.get_liquidation_price().unwrap();
+
+        assert!((liquidation_price - 8.4).abs() < 1e-9);
+
+        position.update(&BidAsk::new_synthetic("ATOMUSDT".into(), liquidation_price, liquidation_price), &instruments);
+
+        assert!((position.current_loss_percent - position.order.stop_out_percent).abs() < 1e-9);
+    }
+
+    #[test]
+    fn move_stop_loss_to_break_even() {
+        let instruments = new_instruments();
+        let mut prices = SortedVec::new();
+        prices.insert_or_replace(AssetPrice {price: 1.0, symbol: "USDT".into()});
+        let mut invest_assets = SortedVec::new();
+        invest_assets.insert_or_replace(assets::AssetAmount {amount: 100.0, symbol: "USDT".into()});
+        let mut order = new_order("ATOMUSDT".into(), invest_assets, 2.0, OrderSide::Buy);
+        order.stop_loss = Some(StopLossConfig {
+            value: 9.0,
+            unit: AutoClosePositionUnit::PriceRateUnit,
+            break_even_profit: Some(10.0),
+        });
+        let mut position = new_active_position(order, &BidAsk::new_synthetic("ATOMUSDT".into(), 10.0, 10.0), &prices);
+
+        position.update(&BidAsk::new_synthetic("ATOMUSDT".into(), 10.4, 10.4), &instruments);
+        assert!(!position.is_break_even_reached);
+
+        position.update(&BidAsk::new_synthetic("ATOMUSDT".into(), 11.0, 11.0), &instruments);
+        assert!(position.is_break_even_reached);
+        assert!((position.get_break_even_price().unwrap() - 10.0).abs() < 1e-9);
+        assert!((position.break_even_price.unwrap() - 10.0).abs() < 1e-9);
+        assert!((position.stop_loss_price.unwrap() - 10.0).abs() < 1e-9);
+
+        position.update(&BidAsk::new_synthetic("ATOMUSDT".into(), 10.5, 10.5), &instruments);
+        assert!(position.determine_close_reason().is_none());
+
+        position.update(&BidAsk::new_synthetic("ATOMUSDT".into(), 9.99, 9.99), &instruments);
+        assert!(matches!(position.determine_close_reason(), Some(ClosePositionReason::StopLoss)));
+
+        let mut top_up_assets = SortedVec::new();
+        top_up_assets.insert_or_replace(AssetAmount {amount: 100.0, symbol: "USDT".into()});
+        position.add_top_up(ActiveTopUp {
+            id: "1".to_string(),
+            date: DateTimeAsMicroseconds::now(),
+            total_assets: top_up_assets,
+            instrument_price: 12.0,
+            asset_prices: prices,
+            bonus_assets: SortedVec::new(),
+        });
+
+        // 200 * (price / 10 - 1) + 200 * (price / 12 - 1) is zero
+        let break_even_price = 400.0 / (20.0 + 200.0 / 12.0);
+        assert!((position.break_even_price.unwrap() - break_even_price).abs() < 1e-9);
+        assert!((position.stop_loss_price.unwrap() - break_even_price).abs() < 1e-9);
+    }
+
+    #[test]
+    fn reverse_position() {
+        let mut prices = SortedVec::new();
+        prices.insert_or_replace(AssetPrice {price: 1.0, symbol: "USDT".into()});
+        let mut invest_assets = SortedVec::new();
+        invest_assets.insert_or_replace(assets::AssetAmount {amount: 100.0, symbol: "USDT".into()});
+        let mut order = new_order("ATOMUSDT".into(), invest_assets, 2.0, OrderSide::Buy);
+        order.stop_loss = Some(StopLossConfig {value: 5.0, unit: AutoClosePositionUnit::PriceRateUnit, break_even_profit: None});
+        let position = new_active_position(order, &BidAsk::new_synthetic("ATOMUSDT".into(), 10.0, 10.0), &prices);
+
+        let (closed, reversed) = position
+            .reverse(&BidAsk::new_synthetic("ATOMUSDT".into(), 12.0, 12.0), None, &new_instruments(), &SystemClock)
+            .unwrap();
+
+        assert_eq!(closed.close_price, 12.0);
+        assert!((closed.pnl.unwrap() - 40.0).abs() < 1e-9);
+        assert_eq!(reversed.order.side, OrderSide::Sell);
+        assert_eq!(reversed.open_price, 12.0);
+        assert_eq!(reversed.total_invest_assets.get(&"USDT".into()).unwrap().amount, 100.0);
+        assert!(reversed.order.stop_loss.is_none());
+        assert_ne!(reversed.id, closed.id);
+    }
+
     #[tokio::test]
     async fn calc_pnl_with_top_ups_2() {
         let instrument: InstrumentSymbol = "ATOMUSDT".into();
@@ -925,7 +2485,7 @@ mod tests {
             bid: 0.37,
             datetime: DateTimeAsMicroseconds::now(),
             instrument,
-        });
+        }, &new_instruments());
 
         assert_eq!(0.0, position.current_pnl);
     }
@@ -985,7 +2545,7 @@ mod tests {
             bid: 0.37,
             datetime: DateTimeAsMicroseconds::now(),
             instrument,
-        });
+        }, &new_instruments());
 
         println!("{}", position.current_pnl);
 
@@ -1000,14 +2560,15 @@ mod tests {
         let mut invest_assets = SortedVec::new();
         invest_assets.insert_or_replace(assets::AssetAmount {amount: 100342.0, symbol: "USDT".into()});
         let mut order = new_order(instrument.clone(), invest_assets, 1.0, OrderSide::Buy);
-        order.desire_price = Some(26000.00);
+        order.order_type = OrderType::Stop;
+        order.stop_price = Some(26000.00);
         let bidask = BidAsk {
             ask: 25900.00,
             bid: 25900.00,
             datetime: DateTimeAsMicroseconds::now(),
             instrument,
         };
-        let position = order.open(&bidask, &prices);
+        let position = order.open(&bidask, &prices, &OpenContext::new(&new_instruments(), &CommissionSchedules::new(), &SystemClock));
         let Position::Pending(pending_position) = position else {
             panic!("Must be pending position");
         };
@@ -1026,14 +2587,15 @@ mod tests {
         invest_assets.insert_or_replace(assets::AssetAmount {amount: 100342.0, symbol: "USDT".into()});
         
         let mut order = new_order(instrument.clone(), invest_assets, 1.0, OrderSide::Buy);
-        order.desire_price = Some(26000.00);
+        order.order_type = OrderType::Stop;
+        order.stop_price = Some(26000.00);
         let bidask = BidAsk {
             ask: 25900.00,
             bid: 25900.00,
             datetime: DateTimeAsMicroseconds::now(),
             instrument,
         };
-        let position = order.open(&bidask, &prices);
+        let position = order.open(&bidask, &prices, &OpenContext::new(&new_instruments(), &CommissionSchedules::new(), &SystemClock));
         let Position::Pending(mut pending_position) = position else {
             panic!("Must be pending position");
         };
@@ -1053,6 +2615,7 @@ mod tests {
         invest_assets.insert_or_replace(assets::AssetAmount {amount: 100342.0, symbol: "USDT".into()});
         
         let mut order = new_order(instrument.clone(), invest_assets, 1.0, OrderSide::Buy);
+        order.order_type = OrderType::Limit;
         order.desire_price = Some(25000.00);
         let bidask = BidAsk {
             ask: 25900.00,
@@ -1060,7 +2623,7 @@ mod tests {
             datetime: DateTimeAsMicroseconds::now(),
             instrument,
         };
-        let position = order.open(&bidask, &prices);
+        let position = order.open(&bidask, &prices, &OpenContext::new(&new_instruments(), &CommissionSchedules::new(), &SystemClock));
         let Position::Pending(mut pending_position) = position else {
             panic!("Must be pending position");
         };
@@ -1080,6 +2643,7 @@ mod tests {
         invest_assets.insert_or_replace(assets::AssetAmount {amount: 100342.0, symbol: "USDT".into()});
         
         let mut order = new_order(instrument.clone(), invest_assets, 1.0, OrderSide::Buy);
+        order.order_type = OrderType::Limit;
         order.desire_price = Some(25000.00);
         let bidask = BidAsk {
             ask: 25900.00,
@@ -1087,7 +2651,7 @@ mod tests {
             datetime: DateTimeAsMicroseconds::now(),
             instrument,
         };
-        let position = order.open(&bidask, &prices);
+        let position = order.open(&bidask, &prices, &OpenContext::new(&new_instruments(), &CommissionSchedules::new(), &SystemClock));
         let Position::Pending(mut pending_position) = position else {
             panic!("Must be pending position");
         };
@@ -1107,6 +2671,7 @@ mod tests {
         invest_assets.insert_or_replace(assets::AssetAmount {amount: 100342.0, symbol: "USDT".into()});
         
         let mut order = new_order(instrument.clone(), invest_assets, 1.0, OrderSide::Sell);
+        order.order_type = OrderType::Limit;
         order.desire_price = Some(26000.00);
         let bidask = BidAsk {
             ask: 25900.00,
@@ -1114,7 +2679,7 @@ mod tests {
             datetime: DateTimeAsMicroseconds::now(),
             instrument,
         };
-        let position = order.open(&bidask, &prices);
+        let position = order.open(&bidask, &prices, &OpenContext::new(&new_instruments(), &CommissionSchedules::new(), &SystemClock));
         let Position::Pending(pending_position) = position else {
             panic!("Must be pending position");
         };
@@ -1133,6 +2698,7 @@ mod tests {
         invest_assets.insert_or_replace(assets::AssetAmount {amount: 100342.0, symbol: "USDT".into()});
         
         let mut order = new_order(instrument.clone(), invest_assets, 1.0, OrderSide::Sell);
+        order.order_type = OrderType::Limit;
         order.desire_price = Some(26000.00);
         let bidask = BidAsk {
             ask: 25900.00,
@@ -1140,7 +2706,7 @@ mod tests {
             datetime: DateTimeAsMicroseconds::now(),
             instrument,
         };
-        let position = order.open(&bidask, &prices);
+        let position = order.open(&bidask, &prices, &OpenContext::new(&new_instruments(), &CommissionSchedules::new(), &SystemClock));
         let Position::Pending(mut pending_position) = position else {
             panic!("Must be pending position");
         };
@@ -1160,14 +2726,15 @@ mod tests {
         invest_assets.insert_or_replace(assets::AssetAmount {amount: 100342.0, symbol: "USDT".into()});
         
         let mut order = new_order(instrument.clone(), invest_assets, 1.0, OrderSide::Sell);
-        order.desire_price = Some(25000.00);
+        order.order_type = OrderType::Stop;
+        order.stop_price = Some(25000.00);
         let bidask = BidAsk {
             ask: 25900.00,
             bid: 25900.00,
             datetime: DateTimeAsMicroseconds::now(),
             instrument,
         };
-        let position = order.open(&bidask, &prices);
+        let position = order.open(&bidask, &prices, &OpenContext::new(&new_instruments(), &CommissionSchedules::new(), &SystemClock));
         let Position::Pending(mut pending_position) = position else {
             panic!("Must be pending position");
         };
@@ -1187,14 +2754,15 @@ mod tests {
         invest_assets.insert_or_replace(assets::AssetAmount {amount: 100342.0, symbol: "USDT".into()});
         
         let mut order = new_order(instrument.clone(), invest_assets, 1.0, OrderSide::Sell);
-        order.desire_price = Some(25000.00);
+        order.order_type = OrderType::Stop;
+        order.stop_price = Some(25000.00);
         let bidask = BidAsk {
             ask: 25900.00,
             bid: 25900.00,
             datetime: DateTimeAsMicroseconds::now(),
             instrument,
         };
-        let position = order.open(&bidask, &prices);
+        let position = order.open(&bidask, &prices, &OpenContext::new(&new_instruments(), &CommissionSchedules::new(), &SystemClock));
         let Position::Pending(mut pending_position) = position else {
             panic!("Must be pending position");
         };
@@ -1205,6 +2773,399 @@ mod tests {
         assert!(!is_price_reached);
     }
 
+    #[test]
+    fn limit_buy_opened_below_desire_price_is_reached() {
+        let mut prices = SortedVec::new();
+        prices.insert_or_replace(AssetPrice {price: 1.0, symbol: "USDT".into()});
+        let mut invest_assets = SortedVec::new();
+        invest_assets.insert_or_replace(assets::AssetAmount {amount: 100.0, symbol: "USDT".into()});
+        let mut order = new_order("ATOMUSDT".into(), invest_assets, 1.0, OrderSide::Buy);
+        order.order_type = OrderType::Limit;
+        order.desire_price = Some(25000.00);
+        let bidask = BidAsk::new_synthetic("ATOMUSDT".into(), 24900.0, 24900.0);
+
+        let Position::Pending(position) = order.open(&bidask, &prices, &OpenContext::new(&new_instruments(), &CommissionSchedules::new(), &SystemClock)) else {
+            panic!("Must be pending position");
+        };
+
+        assert!(position.is_price_reached());
+    }
+
+    #[test]
+    fn stop_limit_buy_waits_for_desire_price_after_stop() {
+        let instruments = new_instruments();
+        let mut prices = SortedVec::new();
+        prices.insert_or_replace(AssetPrice {price: 1.0, symbol: "USDT".into()});
+        let mut invest_assets = SortedVec::new();
+        invest_assets.insert_or_replace(assets::AssetAmount {amount: 100.0, symbol: "USDT".into()});
+        let mut order = new_order("ATOMUSDT".into(), invest_assets.clone(), 1.0, OrderSide::Buy);
+        order.order_type = OrderType::StopLimit;
+        order.stop_price = Some(26000.00);
+        order.desire_price = Some(25500.00);
+        let bidask = BidAsk::new_synthetic("ATOMUSDT".into(), 25000.0, 25000.0);
+        let Position::Pending(mut position) = order.open(&bidask, &prices, &OpenContext::new(&new_instruments(), &CommissionSchedules::new(), &SystemClock)) else {
+            panic!("Must be pending position");
+        };
+        position.add_invest_assets(&invest_assets).unwrap();
+
+        position.update(&BidAsk::new_synthetic("ATOMUSDT".into(), 26100.0, 26100.0), &instruments);
+        assert!(position.is_stop_triggered);
+        assert!(!position.is_price_reached());
+
+        position.update(&BidAsk::new_synthetic("ATOMUSDT".into(), 25400.0, 25400.0), &instruments);
+        assert!(position.is_price_reached());
+    }
+
+    #[test]
+    fn replay_pending_position_by_clock_and_quote_dates() {
+        let instruments = new_instruments();
+        let clock = ManualClock::new(DateTimeAsMicroseconds::new(1_000_000));
+        let mut prices = SortedVec::new();
+        prices.insert_or_replace(AssetPrice {price: 1.0, symbol: "USDT".into()});
+        let mut invest_assets = SortedVec::new();
+        invest_assets.insert_or_replace(assets::AssetAmount {amount: 100.0, symbol: "USDT".into()});
+        let mut order = new_order("ATOMUSDT".into(), invest_assets.clone(), 1.0, OrderSide::Buy);
+        order.order_type = OrderType::Limit;
+        order.desire_price = Some(9.0);
+        let raw_bidask = BidAsk::new_synthetic("ATOMUSDT".into(), 10.0, 10.0);
+        let position = order
+            .try_open(&raw_bidask, &prices, &OpenContext::new(&instruments, &CommissionSchedules::new(), &clock).with_markups(&MarkupProfiles::new()))
+            .unwrap();
+        let Position::Pending(mut position) = position else {
+            panic!("Must be pending position");
+        };
+        position.add_invest_assets(&invest_assets).unwrap();
+
+        let mut bidask = BidAsk::new_synthetic("ATOMUSDT".into(), 8.9, 8.9);
+        bidask.datetime = DateTimeAsMicroseconds::new(2_000_000);
+        position.update(&bidask, &instruments);
+        assert_eq!(position.last_update_date.unix_microseconds, 2_000_000);
+
+        clock.set(DateTimeAsMicroseconds::new(3_000_000));
+        let position = position.activate(None, &clock).unwrap();
+
+        assert_eq!(position.open_date.unix_microseconds, 1_000_000);
+        assert_eq!(position.activate_date.unix_microseconds, 3_000_000);
+    }
+
+    #[test]
+    fn limit_position_is_not_activated_by_depth_above_desire_price() {
+        let mut prices = SortedVec::new();
+        prices.insert_or_replace(AssetPrice {price: 1.0, symbol: "USDT".into()});
+        let mut invest_assets = SortedVec::new();
+        invest_assets.insert_or_replace(assets::AssetAmount {amount: 100.0, symbol: "USDT".into()});
+        let mut order = new_order("ATOMUSDT".into(), invest_assets.clone(), 1.0, OrderSide::Buy);
+        order.order_type = OrderType::Limit;
+        order.desire_price = Some(9.0);
+        let bidask = BidAsk::new_synthetic("ATOMUSDT".into(), 10.0, 10.0);
+        let Position::Pending(mut position) = order.open(&bidask, &prices, &OpenContext::new(&new_instruments(), &CommissionSchedules::new(), &SystemClock)) else {
+            panic!("Must be pending position");
+        };
+        position.add_invest_assets(&invest_assets).unwrap();
+        let bidask = BidAsk::new_synthetic("ATOMUSDT".into(), 8.9, 8.9);
+        position.update(&bidask, &new_instruments());
+        position.update_raw_price(&bidask);
+        // 100 USDT buys 5 units at 8.9 and the rest at 9.5
+        let order_book = OrderBook {
+            instrument: "ATOMUSDT".into(),
+            datetime: DateTimeAsMicroseconds::now(),
+            bids: vec![OrderBookLevel { price: 8.8, volume: 100.0 }],
+            asks: vec![
+                OrderBookLevel { price: 8.9, volume: 5.0 },
+                OrderBookLevel { price: 9.5, volume: 100.0 },
+            ],
+        };
+
+        assert!(position.can_activate(None));
+        assert!(!position.can_activate(Some(&order_book)));
+        assert!(matches!(
+            position.activate(Some(&order_book), &SystemClock),
+            Err(TradingError::PositionStateMismatch(_))
+        ));
+    }
+
+    #[test]
+    fn pending_position_is_not_activated_by_depth_without_asset_price() {
+        let mut prices = SortedVec::new();
+        prices.insert_or_replace(AssetPrice {price: 1.0, symbol: "USDT".into()});
+        let mut invest_assets = SortedVec::new();
+        invest_assets.insert_or_replace(assets::AssetAmount {amount: 100.0, symbol: "USDT".into()});
+        let mut order = new_order("ATOMUSDT".into(), invest_assets.clone(), 1.0, OrderSide::Buy);
+        order.order_type = OrderType::Limit;
+        order.desire_price = Some(9.0);
+        let bidask = BidAsk::new_synthetic("ATOMUSDT".into(), 10.0, 10.0);
+        let Position::Pending(mut position) = order.open(&bidask, &prices, &OpenContext::new(&new_instruments(), &CommissionSchedules::new(), &SystemClock)) else {
+            panic!("Must be pending position");
+        };
+        position.add_invest_assets(&invest_assets).unwrap();
+        position.update(&BidAsk::new_synthetic("ATOMUSDT".into(), 8.9, 8.9), &new_instruments());
+        position.current_asset_prices = SortedVec::new();
+        let order_book = OrderBook {
+            instrument: "ATOMUSDT".into(),
+            datetime: DateTimeAsMicroseconds::now(),
+            bids: vec![OrderBookLevel { price: 8.8, volume: 100.0 }],
+            asks: vec![OrderBookLevel { price: 8.9, volume: 100.0 }],
+        };
+
+        assert!(!position.can_activate(Some(&order_book)));
+        assert!(matches!(
+            position.activate(Some(&order_book), &SystemClock),
+            Err(TradingError::MissingPrice(_))
+        ));
+    }
+
+    #[test]
+    fn day_order_expires_at_end_of_day() {
+        let day = 24 * 60 * 60 * 1_000_000;
+        let mut invest_assets = SortedVec::new();
+        invest_assets.insert_or_replace(assets::AssetAmount {amount: 100.0, symbol: "USDT".into()});
+        let mut order = new_order("ATOMUSDT".into(), invest_assets, 1.0, OrderSide::Buy);
+        order.created_date = DateTimeAsMicroseconds::new(3 * day + 100);
+        order.time_in_force = TimeInForce::Day;
+
+        let expiration_date = order.get_expiration_date().unwrap();
+
+        assert_eq!(expiration_date.unix_microseconds, 4 * day);
+    }
+
+    #[test]
+    fn try_open_limit_without_desire_price() {
+        let mut prices = SortedVec::new();
+        prices.insert_or_replace(AssetPrice {price: 1.0, symbol: "USDT".into()});
+        let mut invest_assets = SortedVec::new();
+        invest_assets.insert_or_replace(assets::AssetAmount {amount: 100.0, symbol: "USDT".into()});
+        let mut order = new_order("ATOMUSDT".into(), invest_assets, 1.0, OrderSide::Buy);
+        order.order_type = OrderType::Limit;
+        let bidask = BidAsk::new_synthetic("ATOMUSDT".into(), 25000.0, 25000.0);
+
+        let result = order.try_open(&bidask, &prices, &OpenContext::new(&new_instruments(), &CommissionSchedules::new(), &SystemClock));
+
+        assert!(matches!(result, Err(TradingError::InvalidOrder(_))));
+    }
+
+    #[test]
+    fn validate_order_returns_all_violations() {
+        let mut prices = SortedVec::new();
+        prices.insert_or_replace(AssetPrice {price: 1.0, symbol: "USDT".into()});
+        let mut invest_assets = SortedVec::new();
+        invest_assets.insert_or_replace(assets::AssetAmount {amount: -1.0, symbol: "USDT".into()});
+        let mut order = new_order("ATOMUSDT".into(), invest_assets, 200.0, OrderSide::Buy);
+        order.margin_call_percent = 95.0;
+        order.take_profit = Some(TakeProfitConfig {
+            unit: crate::orders::AutoClosePositionUnit::PriceRateUnit,
+            value: 13.0,
+        });
+        let bidask = BidAsk::new_synthetic("ATOMUSDT".into(), 14.0, 14.1);
+
+        let violations = order.validate(&bidask, &prices, Some(100.0), &new_instruments()).unwrap_err();
+
+        assert_eq!(
+            violations,
+            vec![
+                OrderViolation::InvalidLeverage { leverage: 200.0, max_leverage: Some(100.0) },
+                OrderViolation::StopOutNotAboveMarginCall { stop_out_percent: 90.0, margin_call_percent: 95.0 },
+                OrderViolation::InvalidInvestAmount { symbol: "USDT".into(), amount: -1.0 },
+                OrderViolation::InvalidTakeProfit { value: 13.0, close_price: 14.0 },
+            ]
+        );
+    }
+
+    #[test]
+    fn validate_order_price_sides() {
+        let mut prices = SortedVec::new();
+        prices.insert_or_replace(AssetPrice {price: 1.0, symbol: "USDT".into()});
+        let mut invest_assets = SortedVec::new();
+        invest_assets.insert_or_replace(assets::AssetAmount {amount: 100.0, symbol: "USDT".into()});
+        let bidask = BidAsk::new_synthetic("ATOMUSDT".into(), 14.0, 14.1);
+        let new_pending_order = |order_type: OrderType, side: OrderSide, stop_price: Option<f64>, desire_price: Option<f64>| {
+            let mut order = new_order("ATOMUSDT".into(), invest_assets.clone(), 1.0, side);
+            order.order_type = order_type;
+            order.stop_price = stop_price;
+            order.desire_price = desire_price;
+
+            order
+        };
+
+        let limit_buy = new_pending_order(OrderType::Limit, OrderSide::Buy, None, Some(14.2));
+        assert_eq!(
+            limit_buy.validate(&bidask, &prices, None, &new_instruments()).unwrap_err(),
+            vec![OrderViolation::InvalidDesirePriceSide { desire_price: 14.2, open_price: 14.1 }]
+        );
+
+        let limit_sell = new_pending_order(OrderType::Limit, OrderSide::Sell, None, Some(14.2));
+        assert!(limit_sell.validate(&bidask, &prices, None, &new_instruments()).is_ok());
+
+        let stop_sell = new_pending_order(OrderType::Stop, OrderSide::Sell, Some(14.05), None);
+        assert_eq!(
+            stop_sell.validate(&bidask, &prices, None, &new_instruments()).unwrap_err(),
+            vec![OrderViolation::InvalidStopPriceSide { stop_price: 14.05, open_price: 14.0 }]
+        );
+
+        let stop_buy = new_pending_order(OrderType::Stop, OrderSide::Buy, Some(14.5), None);
+        assert!(stop_buy.validate(&bidask, &prices, None, &new_instruments()).is_ok());
+
+        let stop_limit_buy = new_pending_order(OrderType::StopLimit, OrderSide::Buy, Some(14.5), Some(14.6));
+        assert_eq!(
+            stop_limit_buy.validate(&bidask, &prices, None, &new_instruments()).unwrap_err(),
+            vec![OrderViolation::InvalidStopLimitPrice { stop_price: 14.5, desire_price: 14.6 }]
+        );
+
+        let stop_limit_sell = new_pending_order(OrderType::StopLimit, OrderSide::Sell, Some(13.5), Some(13.7));
+        assert!(stop_limit_sell.validate(&bidask, &prices, None, &new_instruments()).is_ok());
+    }
+
+    #[test]
+    fn try_open_with_invalid_leverage() {
+        let mut prices = SortedVec::new();
+        prices.insert_or_replace(AssetPrice {price: 1.0, symbol: "USDT".into()});
+        let mut invest_assets = SortedVec::new();
+        invest_assets.insert_or_replace(assets::AssetAmount {amount: 100.0, symbol: "USDT".into()});
+        let order = new_order("ATOMUSDT".into(), invest_assets, 0.0, OrderSide::Buy);
+        let bidask = BidAsk::new_synthetic("ATOMUSDT".into(), 14.748, 14.748);
+
+        let result = order.try_open(&bidask, &prices, &OpenContext::new(&new_instruments(), &CommissionSchedules::new(), &SystemClock));
+
+        assert!(matches!(result, Err(TradingError::InvalidLeverage(_))));
+    }
+
+    #[test]
+    fn try_open_without_asset_price() {
+        let prices = SortedVec::new();
+        let mut invest_assets = SortedVec::new();
+        invest_assets.insert_or_replace(assets::AssetAmount {amount: 100.0, symbol: "BTC".into()});
+        let order = new_order("ATOMUSDT".into(), invest_assets, 1.0, OrderSide::Buy);
+        let bidask = BidAsk::new_synthetic("ATOMUSDT".into(), 14.748, 14.748);
+
+        let result = order.try_open(&bidask, &prices, &OpenContext::new(&new_instruments(), &CommissionSchedules::new(), &SystemClock));
+
+        assert_eq!(result.err(), Some(TradingError::MissingPrice("BTC".to_string())));
+    }
+
+    #[test]
+    fn try_get_asset_price_for_unknown_asset() {
+        let bidask = BidAsk::new_synthetic("ATOMUSDT".into(), 14.748, 14.748);
+
+        let result = bidask.try_get_asset_price(&new_instruments(), &"BTC".into(), &OrderSide::Sell);
+
+        assert!(matches!(result, Err(TradingError::InvalidInstrument(_))));
+    }
+
+    #[test]
+    fn open_with_markups_keeps_raw_prices() {
+        let mut prices = SortedVec::new();
+        prices.insert_or_replace(AssetPrice {price: 1.0, symbol: "USDT".into()});
+        let mut invest_assets = SortedVec::new();
+        invest_assets.insert_or_replace(assets::AssetAmount {amount: 100.0, symbol: "USDT".into()});
+        let mut order = new_order("ATOMUSDT".into(), invest_assets, 1.0, OrderSide::Buy);
+        order.trader_group = Some("retail".to_string());
+        let mut markups = MarkupProfiles::new();
+        markups.add(Some("retail"), MarkupProfile {
+            instrument: "ATOMUSDT".into(),
+            fixed_points: 5.0,
+            point_size: 0.001,
+            percent: 0.0,
+            min_spread: 0.0,
+        });
+        let raw_bidask = BidAsk::new_synthetic("ATOMUSDT".into(), 14.0, 14.0);
+
+        let position = order.try_open(&raw_bidask, &prices, &OpenContext::new(&new_instruments(), &CommissionSchedules::new(), &SystemClock).with_markups(&markups)).unwrap();
+
+        let Position::Active(position) = position else {
+            panic!("Must be active position");
+        };
+        assert_eq!(position.open_price, 14.005);
+        assert_eq!(position.current_price, 13.995);
+        assert_eq!(position.open_raw_price, 14.0);
+        assert_eq!(position.current_raw_price, 14.0);
+    }
+
+    #[test]
+    fn open_with_depth() {
+        let mut prices = SortedVec::new();
+        prices.insert_or_replace(AssetPrice {price: 1.0, symbol: "USDT".into()});
+        let mut invest_assets = SortedVec::new();
+        // 2 units at top of book price
+        invest_assets.insert_or_replace(assets::AssetAmount {amount: 200.0, symbol: "USDT".into()});
+        let order = new_order("ATOMUSDT".into(), invest_assets, 1.0, OrderSide::Buy);
+        let bidask = BidAsk::new_synthetic("ATOMUSDT".into(), 99.0, 100.0);
+        let order_book = OrderBook {
+            instrument: "ATOMUSDT".into(),
+            datetime: DateTimeAsMicroseconds::now(),
+            bids: vec![OrderBookLevel { price: 99.0, volume: 1.0 }],
+            asks: vec![
+                OrderBookLevel { price: 100.0, volume: 1.0 },
+                OrderBookLevel { price: 200.0, volume: 1.0 },
+            ],
+        };
+
+        let position = order
+            .try_open(&bidask, &prices, &OpenContext::new(&new_instruments(), &CommissionSchedules::new(), &SystemClock).with_order_book(&order_book))
+            .unwrap();
+
+        let Position::Active(position) = position else {
+            panic!("Must be active position");
+        };
+        assert_eq!(position.open_price, 150.0);
+        assert_eq!(position.activate_price, 150.0);
+    }
+
+    #[test]
+    fn open_and_close_with_depth_and_markups() {
+        let mut prices = SortedVec::new();
+        prices.insert_or_replace(AssetPrice {price: 1.0, symbol: "USDT".into()});
+        let mut invest_assets = SortedVec::new();
+        invest_assets.insert_or_replace(assets::AssetAmount {amount: 200.0, symbol: "USDT".into()});
+        let mut order = new_order("ATOMUSDT".into(), invest_assets, 1.0, OrderSide::Buy);
+        order.trader_group = Some("retail".to_string());
+        let mut markups = MarkupProfiles::new();
+        markups.add(Some("retail"), MarkupProfile {
+            instrument: "ATOMUSDT".into(),
+            fixed_points: 5.0,
+            point_size: 0.01,
+            percent: 0.0,
+            min_spread: 0.0,
+        });
+        let raw_bidask = BidAsk::new_synthetic("ATOMUSDT".into(), 99.0, 100.0);
+        let order_book = OrderBook {
+            instrument: "ATOMUSDT".into(),
+            datetime: DateTimeAsMicroseconds::now(),
+            bids: vec![
+                OrderBookLevel { price: 99.0, volume: 1.0 },
+                OrderBookLevel { price: 89.0, volume: 1.0 },
+            ],
+            asks: vec![
+                OrderBookLevel { price: 100.0, volume: 1.0 },
+                OrderBookLevel { price: 200.0, volume: 1.0 },
+            ],
+        };
+
+        let position = order
+            .try_open(&raw_bidask, &prices, &OpenContext::new(&new_instruments(), &CommissionSchedules::new(), &SystemClock).with_markups(&markups).with_order_book(&order_book))
+            .unwrap();
+
+        let Position::Active(mut position) = position else {
+            panic!("Must be active position");
+        };
+        assert_eq!(position.open_price, 150.05);
+        assert_eq!(position.open_raw_price, 150.0);
+        assert!((position.get_instrument_volume() - 200.0 / 150.05).abs() < 1e-9);
+
+        position.update(&markups.apply(Some("retail"), &raw_bidask), &new_instruments());
+        position.update_raw_price(&raw_bidask);
+        let volume = position.get_instrument_volume();
+        let position = position.close(ClosePositionReason::ClientCommand, None, Some(&order_book), &SystemClock);
+
+        let depth_raw_price = (99.0 + (volume - 1.0) * 89.0) / volume;
+        assert!((position.close_price - (depth_raw_price - 0.05)).abs() < 1e-9);
+    }
+
+    fn new_instruments() -> InstrumentsRegistry {
+        InstrumentsRegistry::new(vec![
+            Instrument::new("ATOMUSDT", "ATOM", "USDT"),
+            Instrument::new("BTCUSDT", "BTC", "USDT"),
+        ])
+    }
+
     fn new_order(
         instrument: InstrumentSymbol,
         invest_assets: SortedVec<AssetSymbol, assets::AssetAmount>,
@@ -1218,7 +3179,10 @@ mod tests {
             trader_id: "test".to_string(),
             wallet_id: Uuid::new_v4().into(),
             created_date: DateTimeAsMicroseconds::now(),
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::GoodTillCanceled,
             desire_price: None,
+            stop_price: None,
             funding_fee_period: None,
             invest_assets,
             leverage,
@@ -1229,6 +3193,8 @@ mod tests {
             margin_call_percent: 70.0,
             top_up_enabled: false,
             top_up_percent: 10.0,
+            trader_group: None,
+            reduce_only: false,
         }
     }
 
@@ -1248,9 +3214,22 @@ mod tests {
             activate_date: now,
             activate_asset_prices: asset_prices.to_owned(),
             current_price: bidask.get_close_price(&order.side),
+            valuation_price: bidask.get_close_price(&order.side),
+            stop_loss_price: None,
+            is_break_even_reached: false,
+            break_even_price: None,
             current_asset_prices: asset_prices.to_owned(),
+            open_raw_price: bidask.get_open_price(&order.side),
+            activate_raw_price: bidask.get_open_price(&order.side),
+            current_raw_price: bidask.get_close_price(&order.side),
             last_update_date: now,
             top_ups: Vec::new(),
+            increases: Vec::new(),
+            commissions: Vec::new(),
+            funding_pnl: 0.0,
+            last_funding_date: now,
+            swap_pnl: 0.0,
+            last_swap_date: now,
             current_pnl: 0.0,
             current_loss_percent: 0.0,
             prev_loss_percent: 0.0,
@@ -1258,6 +3237,8 @@ mod tests {
             total_invest_assets: order.invest_assets.clone(),
             order,
             bonus_invest_assets: SortedVec::new(),
+            instrument_spec: None,
+            commission_schedule: None,
         }
     }
 }
@@ -0,0 +1,191 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+use rust_extensions::date_time::DateTimeAsMicroseconds;
+use crate::clock::Clock;
+use crate::positions::BidAsk;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PriceExtremes {
+    pub min_bid: f64,
+    pub max_bid: f64,
+    pub min_ask: f64,
+    pub max_ask: f64,
+}
+
+/// Bounded buffer of recent quotes of one instrument sorted by quote date
+#[derive(Clone, Debug)]
+pub struct PriceHistory {
+    capacity: usize,
+    items: VecDeque<BidAsk>,
+}
+
+impl PriceHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            items: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Adds quote keeping date order. The oldest quotes are removed over capacity
+    pub fn add(&mut self, bidask: BidAsk) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let is_last = self
+            .items
+            .back()
+            .map(|last| last.datetime.unix_microseconds <= bidask.datetime.unix_microseconds)
+            .unwrap_or(true);
+
+        if is_last {
+            self.items.push_back(bidask);
+        } else {
+            let index = self.items.partition_point(|item| {
+                item.datetime.unix_microseconds <= bidask.datetime.unix_microseconds
+            });
+            self.items.insert(index, bidask);
+        }
+
+        while self.items.len() > self.capacity {
+            self.items.pop_front();
+        }
+    }
+
+    /// Sets capacity removing the oldest quotes over it
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+
+        while self.items.len() > self.capacity {
+            self.items.pop_front();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn get_last(&self) -> Option<&BidAsk> {
+        self.items.back()
+    }
+
+    /// Returns the last quote received at or before datetime
+    pub fn get_at(&self, datetime: DateTimeAsMicroseconds) -> Option<&BidAsk> {
+        let index = self
+            .items
+            .partition_point(|item| item.datetime.unix_microseconds <= datetime.unix_microseconds);
+
+        if index == 0 {
+            return None;
+        }
+
+        self.items.get(index - 1)
+    }
+
+    /// Returns quotes with dates in range including both bounds
+    pub fn get_range(
+        &self,
+        date_from: DateTimeAsMicroseconds,
+        date_to: DateTimeAsMicroseconds,
+    ) -> impl Iterator<Item = &BidAsk> {
+        let start = self
+            .items
+            .partition_point(|item| item.datetime.unix_microseconds < date_from.unix_microseconds);
+
+        self.items
+            .range(start..)
+            .take_while(move |item| item.datetime.unix_microseconds <= date_to.unix_microseconds)
+    }
+
+    /// Calculates min and max prices of quotes with dates in range including both bounds
+    pub fn get_extremes(
+        &self,
+        date_from: DateTimeAsMicroseconds,
+        date_to: DateTimeAsMicroseconds,
+    ) -> Option<PriceExtremes> {
+        let mut extremes: Option<PriceExtremes> = None;
+
+        for item in self.get_range(date_from, date_to) {
+            let Some(extremes) = extremes.as_mut() else {
+                extremes = Some(PriceExtremes {
+                    min_bid: item.bid,
+                    max_bid: item.bid,
+                    min_ask: item.ask,
+                    max_ask: item.ask,
+                });
+                continue;
+            };
+
+            extremes.min_bid = extremes.min_bid.min(item.bid);
+            extremes.max_bid = extremes.max_bid.max(item.bid);
+            extremes.min_ask = extremes.min_ask.min(item.ask);
+            extremes.max_ask = extremes.max_ask.max(item.ask);
+        }
+
+        extremes
+    }
+
+    /// Calculates min and max prices of quotes received during the last period
+    pub fn get_last_extremes(&self, period: Duration, clock: &dyn Clock) -> Option<PriceExtremes> {
+        let now = clock.now();
+
+        self.get_extremes(now.sub(period), now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PriceExtremes, PriceHistory};
+    use crate::positions::BidAsk;
+    use rust_extensions::date_time::DateTimeAsMicroseconds;
+
+    #[test]
+    fn get_price_at_time() {
+        let mut history = PriceHistory::new(10);
+        history.add(new_bidask(10.0, 100));
+        history.add(new_bidask(12.0, 300));
+        history.add(new_bidask(11.0, 200));
+
+        assert!(history.get_at(DateTimeAsMicroseconds::new(99)).is_none());
+        assert_eq!(history.get_at(DateTimeAsMicroseconds::new(250)).unwrap().bid, 11.0);
+        assert_eq!(history.get_at(DateTimeAsMicroseconds::new(300)).unwrap().bid, 12.0);
+    }
+
+    #[test]
+    fn get_extremes_in_range() {
+        let mut history = PriceHistory::new(3);
+
+        for (i, price) in [20.0, 10.0, 14.0, 12.0].into_iter().enumerate() {
+            history.add(new_bidask(price, i as i64 * 100));
+        }
+
+        let extremes = history
+            .get_extremes(DateTimeAsMicroseconds::new(0), DateTimeAsMicroseconds::new(1000))
+            .unwrap();
+
+        assert_eq!(history.len(), 3);
+        assert_eq!(
+            extremes,
+            PriceExtremes {
+                min_bid: 10.0,
+                max_bid: 14.0,
+                min_ask: 11.0,
+                max_ask: 15.0,
+            }
+        );
+    }
+
+    fn new_bidask(bid: f64, unix_microseconds: i64) -> BidAsk {
+        BidAsk {
+            instrument: "BTCUSDT".into(),
+            datetime: DateTimeAsMicroseconds::new(unix_microseconds),
+            bid,
+            ask: bid + 1.0,
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use ahash::AHashMap;
+use crate::instrument_symbol::InstrumentSymbol;
+use crate::positions::BidAsk;
+
+#[derive(Clone, Debug)]
+pub struct QuoteFilterSettings {
+    /// Max change of mid price in percent compared to the last accepted quote
+    pub max_jump_percent: Option<f64>,
+    /// Max spread in percent of mid price
+    pub max_spread_percent: Option<f64>,
+    /// Rejects quotes older than the last accepted quote
+    pub reject_out_of_order: bool,
+    /// Count of quarantined quotes at the new price level required to accept a price jump
+    pub jump_confirmations: usize,
+}
+
+impl Default for QuoteFilterSettings {
+    fn default() -> Self {
+        Self {
+            max_jump_percent: None,
+            max_spread_percent: None,
+            reject_out_of_order: true,
+            jump_confirmations: 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum QuoteRejectReason {
+    /// Bid or ask is NaN or infinite
+    NotFinite,
+    /// Bid or ask is less or equals zero
+    NotPositive,
+    /// Bid is greater than ask
+    Crossed,
+    SpreadTooWide { spread_percent: f64 },
+    PriceJump { jump_percent: f64 },
+    OutOfOrder,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum QuoteCheckResult {
+    Accepted,
+    Rejected(QuoteRejectReason),
+    /// Quote is kept aside until the new price level is confirmed by next quotes
+    Quarantined(QuoteRejectReason),
+}
+
+impl QuoteCheckResult {
+    pub fn is_accepted(&self) -> bool {
+        matches!(self, QuoteCheckResult::Accepted)
+    }
+}
+
+#[derive(Clone, Debug)]
+struct QuarantinedQuote {
+    bidask: BidAsk,
+    confirmations: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct QuoteFilter {
+    default_settings: QuoteFilterSettings,
+    settings_by_instruments: AHashMap<InstrumentSymbol, QuoteFilterSettings>,
+    quarantined: AHashMap<InstrumentSymbol, QuarantinedQuote>,
+}
+
+impl QuoteFilter {
+    pub fn new(default_settings: QuoteFilterSettings) -> Self {
+        Self {
+            default_settings,
+            settings_by_instruments: AHashMap::new(),
+            quarantined: AHashMap::new(),
+        }
+    }
+
+    pub fn set_settings(&mut self, instrument: InstrumentSymbol, settings: QuoteFilterSettings) {
+        self.settings_by_instruments.insert(instrument, settings);
+    }
+
+    pub fn get_settings(&self, instrument: &InstrumentSymbol) -> &QuoteFilterSettings {
+        self.settings_by_instruments
+            .get(instrument)
+            .unwrap_or(&self.default_settings)
+    }
+
+    /// Returns the last quarantined quote of instrument
+    pub fn get_quarantined(&self, instrument: &InstrumentSymbol) -> Option<&BidAsk> {
+        self.quarantined.get(instrument).map(|item| &item.bidask)
+    }
+
+    /// Checks quote against the last accepted quote of the same instrument
+    pub fn check(&mut self, last_bidask: Option<&BidAsk>, bidask: &BidAsk) -> QuoteCheckResult {
+        let settings = self
+            .settings_by_instruments
+            .get(&bidask.instrument)
+            .unwrap_or(&self.default_settings);
+
+        if let Err(reason) = check_prices(settings, bidask) {
+            return QuoteCheckResult::Rejected(reason);
+        }
+
+        let Some(last_bidask) = last_bidask else {
+            self.quarantined.remove(&bidask.instrument);
+            return QuoteCheckResult::Accepted;
+        };
+
+        if settings.reject_out_of_order
+            && bidask.datetime.unix_microseconds < last_bidask.datetime.unix_microseconds
+        {
+            return QuoteCheckResult::Rejected(QuoteRejectReason::OutOfOrder);
+        }
+
+        let Some(max_jump_percent) = settings.max_jump_percent else {
+            return QuoteCheckResult::Accepted;
+        };
+
+        let jump_percent = calculate_jump_percent(last_bidask, bidask);
+
+        if jump_percent <= max_jump_percent {
+            self.quarantined.remove(&bidask.instrument);
+            return QuoteCheckResult::Accepted;
+        }
+
+        let jump_confirmations = settings.jump_confirmations;
+        let quarantined = self.quarantined.get_mut(&bidask.instrument);
+
+        let confirmations = match quarantined {
+            Some(quarantined)
+                if calculate_jump_percent(&quarantined.bidask, bidask) <= max_jump_percent =>
+            {
+                quarantined.bidask = bidask.clone();
+                quarantined.confirmations += 1;
+                quarantined.confirmations
+            }
+            _ => {
+                let quarantined = QuarantinedQuote {
+                    bidask: bidask.clone(),
+                    confirmations: 0,
+                };
+                self.quarantined.insert(bidask.instrument.clone(), quarantined);
+                0
+            }
+        };
+
+        if confirmations >= jump_confirmations {
+            self.quarantined.remove(&bidask.instrument);
+            return QuoteCheckResult::Accepted;
+        }
+
+        QuoteCheckResult::Quarantined(QuoteRejectReason::PriceJump { jump_percent })
+    }
+}
+
+fn check_prices(settings: &QuoteFilterSettings, bidask: &BidAsk) -> Result<(), QuoteRejectReason> {
+    if !bidask.bid.is_finite() || !bidask.ask.is_finite() {
+        return Err(QuoteRejectReason::NotFinite);
+    }
+
+    if bidask.bid <= 0.0 || bidask.ask <= 0.0 {
+        return Err(QuoteRejectReason::NotPositive);
+    }
+
+    if bidask.bid > bidask.ask {
+        return Err(QuoteRejectReason::Crossed);
+    }
+
+    if let Some(max_spread_percent) = settings.max_spread_percent {
+        let mid = (bidask.bid + bidask.ask) / 2.0;
+        let spread_percent = (bidask.ask - bidask.bid) / mid * 100.0;
+
+        if spread_percent > max_spread_percent {
+            return Err(QuoteRejectReason::SpreadTooWide { spread_percent });
+        }
+    }
+
+    Ok(())
+}
+
+fn calculate_jump_percent(from_bidask: &BidAsk, to_bidask: &BidAsk) -> f64 {
+    let from_mid = (from_bidask.bid + from_bidask.ask) / 2.0;
+    let to_mid = (to_bidask.bid + to_bidask.ask) / 2.0;
+
+    ((to_mid - from_mid) / from_mid * 100.0).abs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{QuoteCheckResult, QuoteFilter, QuoteFilterSettings, QuoteRejectReason};
+    use crate::positions::BidAsk;
+
+    #[test]
+    fn reject_invalid_prices() {
+        let mut filter = QuoteFilter::new(QuoteFilterSettings::default());
+
+        let nan = filter.check(None, &BidAsk::new_synthetic("BTCUSDT".into(), f64::NAN, 1.0));
+        let zero = filter.check(None, &BidAsk::new_synthetic("BTCUSDT".into(), 0.0, 1.0));
+        let crossed = filter.check(None, &BidAsk::new_synthetic("BTCUSDT".into(), 2.0, 1.0));
+
+        assert_eq!(nan, QuoteCheckResult::Rejected(QuoteRejectReason::NotFinite));
+        assert_eq!(zero, QuoteCheckResult::Rejected(QuoteRejectReason::NotPositive));
+        assert_eq!(crossed, QuoteCheckResult::Rejected(QuoteRejectReason::Crossed));
+    }
+
+    #[test]
+    fn quarantine_jump_until_confirmed() {
+        let mut filter = QuoteFilter::new(QuoteFilterSettings {
+            max_jump_percent: Some(5.0),
+            max_spread_percent: Some(1.0),
+            reject_out_of_order: true,
+            jump_confirmations: 2,
+        });
+        let last_bidask = BidAsk::new_synthetic("BTCUSDT".into(), 100.0, 100.0);
+        let spike = BidAsk::new_synthetic("BTCUSDT".into(), 150.0, 150.0);
+
+        let first = filter.check(Some(&last_bidask), &spike);
+        let second = filter.check(Some(&last_bidask), &spike);
+        let third = filter.check(Some(&last_bidask), &spike);
+
+        assert!(matches!(first, QuoteCheckResult::Quarantined(QuoteRejectReason::PriceJump { .. })));
+        assert!(matches!(second, QuoteCheckResult::Quarantined(_)));
+        assert!(third.is_accepted());
+        assert!(filter.get_quarantined(&"BTCUSDT".into()).is_none());
+    }
+}
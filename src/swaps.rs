@@ -0,0 +1,182 @@
+use std::time::Duration;
+use ahash::AHashMap;
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+use rust_extensions::date_time::DateTimeAsMicroseconds;
+use crate::instrument_symbol::InstrumentSymbol;
+use crate::orders::OrderSide;
+
+const DAY_MICROSECONDS: i64 = 24 * 60 * 60 * 1_000_000;
+
+#[derive(Debug, PartialEq, Clone, Copy, IntoPrimitive, TryFromPrimitive)]
+#[repr(i32)]
+pub enum Weekday {
+    Monday = 0,
+    Tuesday = 1,
+    Wednesday = 2,
+    Thursday = 3,
+    Friday = 4,
+    Saturday = 5,
+    Sunday = 6,
+}
+
+impl Weekday {
+    /// Returns UTC weekday of date
+    pub fn from_date(date: DateTimeAsMicroseconds) -> Self {
+        // 1970-01-01 is Thursday
+        let days = date.unix_microseconds.div_euclid(DAY_MICROSECONDS);
+
+        Weekday::try_from(((days + 3).rem_euclid(7)) as i32).expect("Weekday is in range")
+    }
+}
+
+/// Daily swap rates of instrument in percent of position volume charged at every rollover.
+/// Positive rate is paid by position and negative rate is received
+#[derive(Debug, Clone, PartialEq)]
+pub struct SwapRate {
+    pub instrument: InstrumentSymbol,
+    pub long_rate: f64,
+    pub short_rate: f64,
+    /// Rollover of the day is charged three times to cover weekend
+    pub triple_swap_day: Weekday,
+}
+
+impl SwapRate {
+    pub fn get_rate(&self, side: &OrderSide) -> f64 {
+        match side {
+            OrderSide::Buy => self.long_rate,
+            OrderSide::Sell => self.short_rate,
+        }
+    }
+
+    /// Returns count of daily swaps charged at rollover date.
+    /// Weekend rollovers are charged by triple swap day, so they are free
+    pub fn get_multiplier(&self, rollover_date: DateTimeAsMicroseconds) -> u32 {
+        match Weekday::from_date(rollover_date) {
+            weekday if weekday == self.triple_swap_day => 3,
+            Weekday::Saturday | Weekday::Sunday => 0,
+            _ => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SwapRates {
+    /// Rollover time of day since UTC midnight
+    rollover_time: Duration,
+    items: AHashMap<InstrumentSymbol, SwapRate>,
+}
+
+impl SwapRates {
+    pub fn new(rollover_time: Duration) -> Self {
+        Self {
+            rollover_time,
+            items: AHashMap::new(),
+        }
+    }
+
+    pub fn set_rollover_time(&mut self, rollover_time: Duration) {
+        self.rollover_time = rollover_time;
+    }
+
+    pub fn get_rollover_time(&self) -> Duration {
+        self.rollover_time
+    }
+
+    pub fn update(&mut self, rate: SwapRate) {
+        self.items.insert(rate.instrument.clone(), rate);
+    }
+
+    pub fn remove(&mut self, instrument: &InstrumentSymbol) -> Option<SwapRate> {
+        self.items.remove(instrument)
+    }
+
+    pub fn get(&self, instrument: &InstrumentSymbol) -> Option<&SwapRate> {
+        self.items.get(instrument)
+    }
+
+    /// Returns rollover dates after date_from up to date_to including it
+    pub fn get_rollover_dates(
+        &self,
+        date_from: DateTimeAsMicroseconds,
+        date_to: DateTimeAsMicroseconds,
+    ) -> impl Iterator<Item = DateTimeAsMicroseconds> {
+        let offset = self.rollover_time.as_micros() as i64 % DAY_MICROSECONDS;
+        let day_rollover = (date_from.unix_microseconds - offset).div_euclid(DAY_MICROSECONDS)
+            * DAY_MICROSECONDS
+            + offset;
+        let first_rollover = day_rollover + DAY_MICROSECONDS;
+        let date_to = date_to.unix_microseconds;
+
+        (0..)
+            .map(move |day| first_rollover + day * DAY_MICROSECONDS)
+            .take_while(move |rollover| *rollover <= date_to)
+            .map(DateTimeAsMicroseconds::new)
+    }
+}
+
+impl Default for SwapRates {
+    fn default() -> Self {
+        Self::new(Duration::ZERO)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SwapRate, SwapRates, Weekday};
+    use rust_extensions::date_time::DateTimeAsMicroseconds;
+    use std::time::Duration;
+
+    const HOUR: i64 = 60 * 60 * 1_000_000;
+
+    #[test]
+    fn get_rollovers_with_triple_swap() {
+        let rates = SwapRates::new(Duration::from_secs(22 * 60 * 60));
+        let rate = SwapRate {
+            instrument: "EURUSD".into(),
+            long_rate: 0.01,
+            short_rate: -0.005,
+            triple_swap_day: Weekday::Wednesday,
+        };
+        // 2024-01-01 00:00 UTC is Monday
+        let monday = 1_704_067_200_000_000;
+
+        let rollovers: Vec<DateTimeAsMicroseconds> = rates
+            .get_rollover_dates(
+                DateTimeAsMicroseconds::new(monday + 22 * HOUR),
+                DateTimeAsMicroseconds::new(monday + 3 * 24 * HOUR),
+            )
+            .collect();
+
+        assert_eq!(Weekday::from_date(DateTimeAsMicroseconds::new(monday)), Weekday::Monday);
+        assert_eq!(rollovers.len(), 2);
+        assert_eq!(rollovers[0].unix_microseconds, monday + 46 * HOUR);
+        assert_eq!(rate.get_multiplier(rollovers[0]), 1);
+        assert_eq!(rate.get_multiplier(rollovers[1]), 3);
+    }
+
+    #[test]
+    fn skip_weekend_rollovers() {
+        let rates = SwapRates::new(Duration::from_secs(22 * 60 * 60));
+        let mut rate = SwapRate {
+            instrument: "EURUSD".into(),
+            long_rate: 0.01,
+            short_rate: -0.005,
+            triple_swap_day: Weekday::Wednesday,
+        };
+        // 2024-01-05 10:00 UTC is Friday
+        let friday = 1_704_067_200_000_000 + 4 * 24 * HOUR + 10 * HOUR;
+        let rollovers: Vec<DateTimeAsMicroseconds> = rates
+            .get_rollover_dates(
+                DateTimeAsMicroseconds::new(friday),
+                DateTimeAsMicroseconds::new(friday + 3 * 24 * HOUR),
+            )
+            .collect();
+
+        assert_eq!(rollovers.len(), 3);
+        assert_eq!(rollovers.iter().map(|date| rate.get_multiplier(*date)).sum::<u32>(), 1);
+
+        rate.triple_swap_day = Weekday::Friday;
+
+        assert_eq!(rollovers.iter().map(|date| rate.get_multiplier(*date)).sum::<u32>(), 3);
+    }
+}
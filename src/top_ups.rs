@@ -2,6 +2,7 @@ use rust_extensions::date_time::DateTimeAsMicroseconds;
 use rust_extensions::sorted_vec::SortedVec;
 use crate::asset_symbol::AssetSymbol;
 use crate::assets::{AssetAmount, AssetPrice};
+use crate::clock::Clock;
 
 #[derive(Debug, Clone)]
 pub struct ActiveTopUp {
@@ -14,7 +15,7 @@ pub struct ActiveTopUp {
 }
 
 impl ActiveTopUp {
-    pub fn cancel(self, instrument_price: f64) -> CanceledTopUp {
+    pub fn cancel(self, instrument_price: f64, clock: &dyn Clock) -> CanceledTopUp {
         CanceledTopUp {
             id: self.id,
             date: self.date,
@@ -22,7 +23,7 @@ impl ActiveTopUp {
             instrument_price: self.instrument_price,
             asset_prices: self.asset_prices,
             cancel_instrument_price: instrument_price,
-            cancel_date: DateTimeAsMicroseconds::now(),
+            cancel_date: clock.now(),
             bonus_assets: self.bonus_assets,
         }
     }
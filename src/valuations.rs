@@ -0,0 +1,102 @@
+use ahash::AHashMap;
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+use crate::instrument_symbol::InstrumentSymbol;
+use crate::orders::OrderSide;
+use crate::positions::BidAsk;
+
+/// Price used to value active positions for pnl, loss percent and stop-out
+#[derive(Debug, Clone, Copy, PartialEq, IntoPrimitive, TryFromPrimitive)]
+#[repr(i32)]
+pub enum ValuationPriceMode {
+    /// Tradable close side of the spread
+    ClosePrice = 0,
+    MidPrice = 1,
+    /// Externally supplied mark price. Close price is used until the first mark price
+    MarkPrice = 2,
+}
+
+/// Valuation price modes by instruments and externally supplied mark prices
+#[derive(Clone, Debug)]
+pub struct ValuationPrices {
+    default_mode: ValuationPriceMode,
+    modes: AHashMap<InstrumentSymbol, ValuationPriceMode>,
+    mark_prices: AHashMap<InstrumentSymbol, f64>,
+}
+
+impl ValuationPrices {
+    pub fn new(default_mode: ValuationPriceMode) -> Self {
+        Self {
+            default_mode,
+            modes: AHashMap::new(),
+            mark_prices: AHashMap::new(),
+        }
+    }
+
+    pub fn set_mode(&mut self, instrument: InstrumentSymbol, mode: ValuationPriceMode) {
+        self.modes.insert(instrument, mode);
+    }
+
+    pub fn remove_mode(&mut self, instrument: &InstrumentSymbol) -> Option<ValuationPriceMode> {
+        self.modes.remove(instrument)
+    }
+
+    pub fn get_mode(&self, instrument: &InstrumentSymbol) -> ValuationPriceMode {
+        self.modes
+            .get(instrument)
+            .copied()
+            .unwrap_or(self.default_mode)
+    }
+
+    pub fn update_mark_price(&mut self, instrument: InstrumentSymbol, price: f64) {
+        self.mark_prices.insert(instrument, price);
+    }
+
+    pub fn remove_mark_price(&mut self, instrument: &InstrumentSymbol) -> Option<f64> {
+        self.mark_prices.remove(instrument)
+    }
+
+    pub fn get_mark_price(&self, instrument: &InstrumentSymbol) -> Option<f64> {
+        self.mark_prices.get(instrument).copied()
+    }
+
+    /// Returns valuation price of position side by instrument mode
+    pub fn get_price(&self, bidask: &BidAsk, side: &OrderSide) -> f64 {
+        match self.get_mode(&bidask.instrument) {
+            ValuationPriceMode::ClosePrice => bidask.get_close_price(side),
+            ValuationPriceMode::MidPrice => (bidask.bid + bidask.ask) / 2.0,
+            ValuationPriceMode::MarkPrice => self
+                .get_mark_price(&bidask.instrument)
+                .unwrap_or_else(|| bidask.get_close_price(side)),
+        }
+    }
+}
+
+impl Default for ValuationPrices {
+    fn default() -> Self {
+        Self::new(ValuationPriceMode::ClosePrice)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ValuationPriceMode, ValuationPrices};
+    use crate::orders::OrderSide;
+    use crate::positions::BidAsk;
+
+    #[test]
+    fn get_price_by_mode() {
+        let mut prices = ValuationPrices::default();
+        let bidask = BidAsk::new_synthetic("BTCUSDT".into(), 99.0, 101.0);
+
+        assert_eq!(prices.get_price(&bidask, &OrderSide::Buy), 99.0);
+
+        prices.set_mode("BTCUSDT".into(), ValuationPriceMode::MidPrice);
+        assert_eq!(prices.get_price(&bidask, &OrderSide::Buy), 100.0);
+
+        prices.set_mode("BTCUSDT".into(), ValuationPriceMode::MarkPrice);
+        assert_eq!(prices.get_price(&bidask, &OrderSide::Sell), 101.0);
+
+        prices.update_mark_price("BTCUSDT".into(), 100.5);
+        assert_eq!(prices.get_price(&bidask, &OrderSide::Sell), 100.5);
+    }
+}
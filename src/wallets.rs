@@ -1,4 +1,5 @@
 use crate::calculations::calculate_percent;
+use crate::errors::TradingError;
 use crate::orders::OrderSide;
 use crate::positions::BidAsk;
 use ahash::AHashMap;
@@ -7,6 +8,7 @@ use crate::asset_symbol::AssetSymbol;
 use crate::assets;
 use crate::assets::{AssetAmount, AssetPrice};
 use crate::instrument_symbol::InstrumentSymbol;
+use crate::instruments::InstrumentsRegistry;
 use crate::wallet_id::WalletId;
 
 #[derive(Clone, Debug)]
@@ -95,6 +97,29 @@ impl Wallet {
         }
     }
 
+    /// Releases reserved balance of closed part of instrument position
+    pub fn deduct_top_up_reserved(
+        &mut self,
+        instrument: &InstrumentSymbol,
+        released: &SortedVec<AssetSymbol, AssetAmount>,
+    ) {
+        let Some(reserved) = self.top_up_reserved_balance_by_instruments.get_mut(instrument) else {
+            return;
+        };
+
+        let mut released_balance = 0.0;
+
+        for item in released.iter() {
+            if let Some(price) = self.prices_by_assets.get(&item.symbol) {
+                released_balance += price.price * item.amount;
+            }
+        }
+
+        let released_balance = released_balance.min(*reserved);
+        *reserved -= released_balance;
+        self.total_top_up_reserved_balance -= released_balance;
+    }
+
     pub fn add_top_up_pnl(&mut self, instrument: &InstrumentSymbol, instrument_pnl: f64) {
         let pnl = self.top_up_pnls_by_instruments.get_mut(instrument);
 
@@ -132,14 +157,31 @@ impl Wallet {
             && self.prev_loss_percent < self.margin_call_percent
     }
 
-    pub fn add_balance(&mut self, balance: WalletBalance, bid_ask: &BidAsk) -> Result<(), String> {
-        let instrument_id = BidAsk::get_instrument_symbol(&balance.asset_symbol, &self.estimate_asset);
+    pub fn add_balance(
+        &mut self,
+        balance: WalletBalance,
+        bid_ask: &BidAsk,
+        instruments: &InstrumentsRegistry,
+    ) -> Result<(), TradingError> {
+        let instrument_id = instruments
+            .find_pair(&balance.asset_symbol, &self.estimate_asset)
+            .map(|instrument| &instrument.symbol);
+
+        let Some(instrument_id) = instrument_id else {
+            return Err(TradingError::InvalidInstrument(format!(
+                "not found for {} and {}",
+                balance.asset_symbol, self.estimate_asset
+            )));
+        };
 
-        if bid_ask.instrument != instrument_id {
-            return Err(format!("BidAsk instrument must be {}", instrument_id));
+        if bid_ask.instrument != *instrument_id {
+            return Err(TradingError::InvalidInstrument(format!(
+                "BidAsk instrument must be {}",
+                instrument_id
+            )));
         }
 
-        let price = bid_ask.get_asset_price(&balance.asset_symbol, &OrderSide::Sell);
+        let price = bid_ask.try_get_asset_price(instruments, &balance.asset_symbol, &OrderSide::Sell)?;
         self.prices_by_assets
             .insert_or_replace(assets::AssetPrice {price, symbol: balance.asset_symbol.clone()});
         let estimate_amount = balance.asset_amount * price;
@@ -153,18 +195,15 @@ impl Wallet {
         Ok(())
     }
 
-    pub fn update_balance(&mut self, balance: WalletBalance) -> Result<(), String> {
-        let inner_balance = self.balances_by_instruments.remove(&balance.instrument_symbol);
-
-        let Some(inner_balance) = inner_balance else {
-            return Err("Balance not found".to_string());
+    pub fn update_balance(&mut self, balance: WalletBalance) -> Result<(), TradingError> {
+        let Some(inner_balance) = self.balances_by_instruments.get(&balance.instrument_symbol) else {
+            return Err(TradingError::BalanceNotFound(balance.id));
         };
 
         if !balance.is_locked {
-            let price = self
-                .prices_by_assets
-                .get(&inner_balance.asset_symbol)
-                .expect("invalid add");
+            let Some(price) = self.prices_by_assets.get(&inner_balance.asset_symbol) else {
+                return Err(TradingError::MissingPrice(inner_balance.asset_symbol.to_string()));
+            };
             self.total_unlocked_balance -= inner_balance.asset_amount * price.price;
             self.total_unlocked_balance += balance.asset_amount * price.price;
         }
@@ -174,24 +213,23 @@ impl Wallet {
         Ok(())
     }
 
-    pub fn set_balance_lock(&mut self, balance_id: &str, is_locked: bool) -> Result<(), String> {
+    pub fn set_balance_lock(&mut self, balance_id: &str, is_locked: bool) -> Result<(), TradingError> {
         let inner_balance = self
             .balances_by_instruments
             .iter_mut()
             .find(|b| b.id == balance_id);
 
         let Some(balance) = inner_balance else {
-            return Err("Balance not found".to_string());
+            return Err(TradingError::BalanceNotFound(balance_id.to_string()));
         };
 
         if balance.is_locked == is_locked {
             return Ok(()); // no changes no need to do anything
         }
 
-        let price = self
-            .prices_by_assets
-            .get(&balance.asset_symbol)
-            .expect("invalid add");
+        let Some(price) = self.prices_by_assets.get(&balance.asset_symbol) else {
+            return Err(TradingError::MissingPrice(balance.asset_symbol.to_string()));
+        };
 
         if !balance.is_locked && is_locked {
             self.total_unlocked_balance -= balance.asset_amount * price.price;
@@ -204,11 +242,11 @@ impl Wallet {
         Ok(())
     }
 
-    pub fn update_price(&mut self, bid_ask: &BidAsk) {
+    pub fn update_price(&mut self, bid_ask: &BidAsk, instruments: &InstrumentsRegistry) {
         let balance = self.balances_by_instruments.get(&bid_ask.instrument);
 
         if let Some(balance) = balance {
-            let new_price = bid_ask.get_asset_price(&balance.asset_symbol, &OrderSide::Sell);
+            let new_price = bid_ask.get_asset_price(instruments, &balance.asset_symbol, &OrderSide::Sell);
             let old_price = self
                 .prices_by_assets
                 .get_mut(&balance.asset_symbol)